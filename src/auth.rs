@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use log::debug;
+use indicatif::ProgressBar;
+use git2::{Cred, CredentialType, Repository};
+
+/// Number of credentials offered to a remote before giving up, so that
+/// libgit2 does not keep asking forever when every credential is rejected.
+const MAX_ATTEMPTS: usize = 10;
+
+/// Key files tried when `~/.ssh/config` names none.
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Token variables tried for each host, with the username the host expects.
+const HOST_TOKENS: [(&str, &[&str], &str); 3] = [
+    ("github.com", &["GH_TOKEN", "GITHUB_TOKEN"], "x-access-token"),
+    ("gitlab.com", &["GITLAB_TOKEN"], "oauth2"),
+    ("bitbucket.org", &["BITBUCKET_TOKEN"], "x-token-auth"),
+];
+
+/// Passphrases entered for encrypted keys, shared by every remote in this run.
+fn passphrases() -> &'static Mutex<HashMap<PathBuf, String>> {
+    static PASSPHRASES: OnceLock<Mutex<HashMap<PathBuf, String>>> = OnceLock::new();
+    PASSPHRASES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Hands out credentials for one connection to a remote, trying a different
+/// source each time the remote rejects the previous one.
+///
+/// SSH remotes are offered ssh-agent, then the keys in `~/.ssh`. HTTPS remotes
+/// are offered `GIT_ASKPASS`, tokens from the environment for allowed hosts,
+/// then git's credential helper.
+pub struct Authenticator<'a> {
+    repo: &'a Repository,
+    progress: Option<&'a ProgressBar>,
+    attempts: usize,
+    agent_tried: bool,
+    keys: Option<Vec<PathBuf>>,
+    askpass_tried: bool,
+    token_tried: bool,
+    helper_tried: bool,
+    default_tried: bool,
+}
+
+impl<'a> Authenticator<'a> {
+    /// Starts a new credential chain for the given repository.
+    ///
+    /// `progress` is the progress bar shown while connecting, which is hidden
+    /// while asking for a passphrase.
+    pub fn new(repo: &'a Repository, progress: Option<&'a ProgressBar>) -> Self {
+        Authenticator {
+            repo,
+            progress,
+            attempts: 0,
+            agent_tried: false,
+            keys: None,
+            askpass_tried: false,
+            token_tried: false,
+            helper_tried: false,
+            default_tried: false,
+        }
+    }
+
+    /// Returns the next credential to try, as libgit2's credentials callback.
+    pub fn credentials(&mut self, url: &str, username_from_url: Option<&str>, allowed: CredentialType) -> Result<Cred, git2::Error> {
+        self.attempts += 1;
+        if self.attempts > MAX_ATTEMPTS {
+            return Err(git2::Error::from_str(&format!("Authentication failed for {} after {} attempts", url, MAX_ATTEMPTS)));
+        }
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username_from_url.unwrap_or("git"));
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let username = username_from_url.unwrap_or("git");
+            if let Some(cred) = self.next_ssh_key(username) {
+                return cred;
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if !self.askpass_tried {
+                self.askpass_tried = true;
+                if let Some(cred) = askpass(self.repo, url, username_from_url) {
+                    debug!("Trying credentials from askpass for {}.", url);
+                    return Ok(cred);
+                }
+            }
+            if !self.token_tried {
+                self.token_tried = true;
+                if let Some(cred) = env_token(self.repo, url, username_from_url) {
+                    return cred;
+                }
+            }
+            if !self.helper_tried {
+                self.helper_tried = true;
+                debug!("Trying the credential helper for {}.", url);
+                return Cred::credential_helper(&self.repo.config()?, url, username_from_url);
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && !self.default_tried {
+            self.default_tried = true;
+            return Cred::default();
+        }
+
+        Err(git2::Error::from_str(&format!("No more credentials to try for {}", url)))
+    }
+
+    /// Returns the next SSH credential: the agent first, then each key file.
+    fn next_ssh_key(&mut self, username: &str) -> Option<Result<Cred, git2::Error>> {
+        if !self.agent_tried {
+            self.agent_tried = true;
+            if std::env::var_os("SSH_AUTH_SOCK").is_some() {
+                debug!("Trying ssh-agent.");
+                return Some(Cred::ssh_key_from_agent(username));
+            }
+        }
+
+        let keys = self.keys.get_or_insert_with(ssh_keys);
+        while !keys.is_empty() {
+            let key = keys.remove(0);
+            let passphrase = if is_encrypted(&key) {
+                match passphrase(&key, self.progress) {
+                    Some(passphrase) => Some(passphrase),
+                    None => continue,
+                }
+            } else {
+                None
+            };
+            debug!("Trying SSH key {}.", key.display());
+            return Some(Cred::ssh_key(username, None, &key, passphrase.as_deref()));
+        }
+        None
+    }
+}
+
+/// Lists the private keys to try: the `IdentityFile` entries of `~/.ssh/config`
+/// followed by the default key names, keeping only those that exist.
+fn ssh_keys() -> Vec<PathBuf> {
+    let Some(home) = std::env::home_dir() else {
+        return Vec::new();
+    };
+    let ssh_dir = home.join(".ssh");
+
+    let configured = fs::read_to_string(ssh_dir.join("config")).unwrap_or_default();
+    let mut keys: Vec<PathBuf> = configured
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once(|c: char| c.is_whitespace() || c == '=')?;
+            key.eq_ignore_ascii_case("IdentityFile").then(|| value.trim().trim_matches('"').to_string())
+        })
+        .map(|path| match path.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(path),
+        })
+        .collect();
+    keys.extend(DEFAULT_KEYS.iter().map(|name| ssh_dir.join(name)));
+
+    let mut seen = HashSet::new();
+    keys.retain(|key| key.is_file() && seen.insert(key.clone()));
+    keys
+}
+
+/// Returns whether a private key file is protected by a passphrase.
+fn is_encrypted(key: &Path) -> bool {
+    let Ok(contents) = fs::read_to_string(key) else {
+        return false;
+    };
+    if contents.contains("ENCRYPTED") {
+        return true;
+    }
+    // OpenSSH keys start with `openssh-key-v1\0` and the cipher name, which is
+    // `none` for unencrypted keys. This is that prefix in base64.
+    let body: String = contents.lines().filter(|l| !l.starts_with("-----")).collect();
+    contents.contains("BEGIN OPENSSH PRIVATE KEY") && !body.starts_with("b3BlbnNzaC1rZXktdjEAAAAABG5vbmU")
+}
+
+/// Asks for the passphrase of an encrypted key, once per run.
+///
+/// The progress bar, along with any others shown with it, is hidden while
+/// asking so that it does not draw over the prompt. Returns `None` when there
+/// is no terminal to ask on.
+fn passphrase(key: &Path, progress: Option<&ProgressBar>) -> Option<String> {
+    // Holding the lock keeps prompts from parallel fetches from interleaving
+    let mut passphrases = passphrases().lock().ok()?;
+    if let Some(passphrase) = passphrases.get(key) {
+        return Some(passphrase.clone());
+    }
+    if !std::io::stdin().is_terminal() {
+        debug!("Skipping encrypted key {} without a terminal.", key.display());
+        return None;
+    }
+
+    let prompt = || rpassword::prompt_password(format!("Enter passphrase for {}: ", key.display()));
+    let passphrase = match progress {
+        Some(progress) => progress.suspend(prompt),
+        None => prompt(),
+    }
+    .ok()?;
+    passphrases.insert(key.to_path_buf(), passphrase.clone());
+    Some(passphrase)
+}
+
+/// Asks for a username and password with `GIT_ASKPASS`, `core.askPass` or `SSH_ASKPASS`.
+fn askpass(repo: &Repository, url: &str, username_from_url: Option<&str>) -> Option<Cred> {
+    let program = std::env::var("GIT_ASKPASS").ok()
+        .or_else(|| repo.config().ok()?.get_string("core.askPass").ok())
+        .or_else(|| std::env::var("SSH_ASKPASS").ok())
+        .filter(|program| !program.is_empty())?;
+
+    let ask = |prompt: String| -> Option<String> {
+        let output = Command::new(&program).arg(prompt).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let answer = String::from_utf8(output.stdout).ok()?;
+        Some(answer.trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    let username = match username_from_url {
+        Some(username) => username.to_string(),
+        None => ask(format!("Username for '{}': ", url))?,
+    };
+    let password = ask(format!("Password for '{}': ", url))?;
+    Cred::userpass_plaintext(&username, &password).ok()
+}
+
+/// Builds a credential from a token in the environment.
+///
+/// Tokens are only sent over HTTPS. Well-known variables such as `GITHUB_TOKEN`
+/// are used for their own host, and `PURGIT_TOKEN` for the hosts listed in
+/// `purgit.tokenHost`.
+fn env_token(repo: &Repository, url: &str, username_from_url: Option<&str>) -> Option<Result<Cred, git2::Error>> {
+    let Some(host) = https_host(url) else {
+        debug!("Not sending a token to {} without HTTPS.", url);
+        return None;
+    };
+
+    let (token, username) = select_token(&host, &token_hosts(repo), |var| std::env::var(var).ok())?;
+    debug!("Trying a token from the environment for {}.", url);
+    Some(Cred::userpass_plaintext(username_from_url.unwrap_or(username), &token))
+}
+
+/// Picks the token for `host` from the variables read by `var`, along with the
+/// username the host expects by default.
+///
+/// `PURGIT_TOKEN` is only picked for the `allowed` hosts.
+fn select_token(host: &str, allowed: &[String], var: impl Fn(&str) -> Option<String>) -> Option<(String, &'static str)> {
+    let host_token = HOST_TOKENS.iter().find(|(name, _, _)| *name == host);
+    let token = allowed
+        .iter()
+        .any(|allowed| allowed == host)
+        .then(|| var("PURGIT_TOKEN"))
+        .flatten()
+        .or_else(|| host_token.and_then(|(_, vars, _)| vars.iter().find_map(|name| var(name))))?;
+    Some((token, host_token.map_or("x-access-token", |(_, _, username)| *username)))
+}
+
+/// Returns the hosts `PURGIT_TOKEN` may be sent to, from `purgit.tokenHost`.
+fn token_hosts(repo: &Repository) -> Vec<String> {
+    let mut hosts = Vec::new();
+    if let Ok(config) = repo.config()
+        && let Ok(mut entries) = config.multivar("purgit.tokenHost", None)
+    {
+        while let Some(Ok(entry)) = entries.next() {
+            if let Some(host) = entry.value() {
+                hosts.push(host.trim().to_lowercase());
+            }
+        }
+    }
+    hosts
+}
+
+/// Returns the lowercase host of an `https://` URL, without user or port.
+fn https_host(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn https_host_of_plain_urls() {
+        assert_eq!(https_host("https://github.com/owner/repo.git").as_deref(), Some("github.com"));
+        assert_eq!(https_host("HTTPS://GitHub.COM/owner/repo.git").as_deref(), Some("github.com"));
+        assert_eq!(https_host("https://gitlab.com").as_deref(), Some("gitlab.com"));
+        assert_eq!(https_host("https://github.com?x=1").as_deref(), Some("github.com"));
+    }
+
+    #[test]
+    fn https_host_ignores_user_path_and_port() {
+        assert_eq!(https_host("https://github.com@evil.com/owner/repo.git").as_deref(), Some("evil.com"));
+        assert_eq!(https_host("https://user:p@ss@evil.com/x").as_deref(), Some("evil.com"));
+        assert_eq!(https_host("https://evil.com/github.com").as_deref(), Some("evil.com"));
+        assert_eq!(https_host("https://github.com.evil.com/x").as_deref(), Some("github.com.evil.com"));
+        assert_eq!(https_host("https://github.com:8443/x").as_deref(), Some("github.com"));
+        assert_eq!(https_host("https://x-access-token@github.com:443/x").as_deref(), Some("github.com"));
+    }
+
+    #[test]
+    fn https_host_of_ipv6_urls() {
+        assert_eq!(https_host("https://[::1]/x").as_deref(), Some("::1"));
+        assert_eq!(https_host("https://[2001:db8::1]:8443/x").as_deref(), Some("2001:db8::1"));
+    }
+
+    #[test]
+    fn https_host_requires_https() {
+        assert_eq!(https_host("http://github.com/owner/repo.git"), None);
+        assert_eq!(https_host("ssh://git@github.com/owner/repo.git"), None);
+        assert_eq!(https_host("git@github.com:owner/repo.git"), None);
+        assert_eq!(https_host("github.com/owner/repo.git"), None);
+        assert_eq!(https_host("https:///x"), None);
+    }
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn host_tokens_only_go_to_their_host() {
+        let vars = env(&[("GITHUB_TOKEN", "gh"), ("GITLAB_TOKEN", "gl")]);
+        assert_eq!(select_token("github.com", &[], &vars), Some(("gh".to_string(), "x-access-token")));
+        assert_eq!(select_token("gitlab.com", &[], &vars), Some(("gl".to_string(), "oauth2")));
+        assert_eq!(select_token("github.com.evil.com", &[], &vars), None);
+        assert_eq!(select_token("evil.com", &[], &vars), None);
+        assert_eq!(select_token("bitbucket.org", &[], &vars), None);
+    }
+
+    #[test]
+    fn purgit_token_only_goes_to_allowed_hosts() {
+        let vars = env(&[("PURGIT_TOKEN", "pt"), ("GH_TOKEN", "gh")]);
+        let allowed = vec!["git.example.com".to_string()];
+        assert_eq!(select_token("git.example.com", &allowed, &vars), Some(("pt".to_string(), "x-access-token")));
+        assert_eq!(select_token("evil.com", &allowed, &vars), None);
+        assert_eq!(select_token("example.com", &allowed, &vars), None);
+        assert_eq!(select_token("github.com", &allowed, &vars), Some(("gh".to_string(), "x-access-token")));
+        assert_eq!(select_token("git.example.com", &[], &vars), None);
+    }
+
+    #[test]
+    fn token_hosts_from_git_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(token_hosts(&repo).is_empty());
+
+        let mut config = repo.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap();
+        config.set_multivar("purgit.tokenHost", "^$", "Git.Example.com").unwrap();
+        config.set_multivar("purgit.tokenHost", "^$", " other.example.com ").unwrap();
+        assert_eq!(token_hosts(&repo), vec!["git.example.com".to_string(), "other.example.com".to_string()]);
+    }
+}
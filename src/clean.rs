@@ -0,0 +1,511 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use log::{debug, error};
+
+use colored::*;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use indicatif::ProgressBar;
+use git2::{Branch, BranchType, Commit, Oid, Repository};
+use crate::git_utils::{self, MergeStatus};
+use crate::io_utils;
+use crate::protect::Protection;
+use crate::trash::{Trash, TrashEntry};
+
+/// A branch selected for deletion.
+///
+/// The serialized field names and order are part of the JSON and CSV report
+/// format, so new fields must only ever be appended.
+#[derive(Debug, Default, Serialize)]
+pub struct BranchDetails {
+    pub name: String,
+    pub kind: String,
+    pub remote: Option<String>,
+    pub age: u64,
+    pub tip: String,
+    pub reason: String,
+    pub skipped: Option<String>,
+    pub author: String,
+    pub last_commit_date: String,
+    pub upstream: Option<String>,
+    pub ahead: Option<usize>,
+    pub behind: Option<usize>,
+    pub committer: String,
+    pub base: Option<String>,
+    pub base_ahead: Option<usize>,
+    pub base_behind: Option<usize>,
+    pub unpushed: Option<usize>,
+}
+
+/// Restricts the selection to branches whose tip was authored by someone.
+pub enum AuthorFilter {
+    /// Case-insensitive substring of the author's `Name <email>`.
+    Pattern(String),
+    /// Exact author email address.
+    Email(String),
+}
+
+impl AuthorFilter {
+    fn matches(&self, author: &git2::Signature) -> bool {
+        match self {
+            AuthorFilter::Pattern(pattern) => author.to_string().to_lowercase().contains(&pattern.to_lowercase()),
+            AuthorFilter::Email(email) => author.email().is_some_and(|e| e.eq_ignore_ascii_case(email)),
+        }
+    }
+}
+
+/// Decides which branches are old enough to be stale.
+pub enum AgeCutoff {
+    /// Branches older than the given duration.
+    Stale(Duration),
+    /// Branches whose tip was committed before and/or after the given Unix times.
+    Between { before: Option<i64>, after: Option<i64> },
+}
+
+impl AgeCutoff {
+    fn matches(&self, commit_time: i64, now: u64) -> bool {
+        match *self {
+            AgeCutoff::Stale(stale) => now.saturating_sub(commit_time as u64) > stale.as_secs(),
+            AgeCutoff::Between { before, after } => {
+                before.is_none_or(|b| commit_time < b) && after.is_none_or(|a| commit_time > a)
+            }
+        }
+    }
+}
+
+/// Which timestamp defines the age of a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum AgeBy {
+    /// Committer date of the tip commit.
+    #[default]
+    Committer,
+    /// Author date of the tip commit.
+    Author,
+    /// Last update of the branch reference in its reflog.
+    Reflog,
+    /// Committer date of the first commit after the branch diverged from the main branch.
+    FirstCommit,
+}
+
+impl AgeBy {
+    /// Describes the timestamp for the report header.
+    pub fn description(self) -> &'static str {
+        match self {
+            AgeBy::Committer => "committer date of the last commit",
+            AgeBy::Author => "author date of the last commit",
+            AgeBy::Reflog => "last reflog update",
+            AgeBy::FirstCommit => "date of the first commit on the branch",
+        }
+    }
+}
+
+/// Criteria used to select branches for deletion.
+pub struct Selection<'a> {
+    /// Age cutoff of stale branches, used when no other selector is given.
+    pub age: AgeCutoff,
+    pub age_by: AgeBy,
+    /// Base branch name and tip, when looking for merged branches instead of stale ones.
+    pub base: Option<(String, Oid)>,
+    /// Whether to select local branches whose upstream no longer exists.
+    pub gone: bool,
+    /// Main branch name and tip that branches are compared against.
+    pub mainline: Option<(String, Oid)>,
+    /// Remotes whose branches are scanned.
+    pub remotes: &'a [String],
+    pub author: Option<AuthorFilter>,
+    pub protection: &'a Protection,
+}
+
+/// Outcome of deleting a set of branches or tags.
+#[derive(Debug, Default)]
+pub struct DeleteSummary {
+    pub deleted: usize,
+    pub failed: usize,
+}
+
+/// What is done to the items handled by a [`Deleter`], for its messages.
+#[derive(Debug, Clone, Copy)]
+pub enum Verb {
+    Delete,
+    Drop,
+    Prune,
+}
+
+impl Verb {
+    /// Returns the verb as in `Failed to delete`, `Deleted` and `Deleting`.
+    fn forms(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Verb::Delete => ("delete", "Deleted", "Deleting"),
+            Verb::Drop => ("drop", "Dropped", "Dropping"),
+            Verb::Prune => ("prune", "Pruned", "Pruning"),
+        }
+    }
+}
+
+/// Confirms, performs and reports a series of deletions on one progress bar.
+pub struct Deleter<'a> {
+    verb: Verb,
+    yes: bool,
+    progress: Option<&'a ProgressBar>,
+    verbose: bool,
+    summary: DeleteSummary,
+}
+
+impl<'a> Deleter<'a> {
+    /// Starts a series of deletions, asking for confirmation of each one unless `yes` is set.
+    pub fn new(verb: Verb, yes: bool, progress: Option<&'a ProgressBar>, verbose: bool) -> Self {
+        if yes && let Some(progress) = progress {
+            progress.set_message("");
+            progress.enable_steady_tick(Duration::from_millis(100));
+            progress.reset();
+        }
+        Deleter { verb, yes, progress, verbose, summary: DeleteSummary::default() }
+    }
+
+    /// Asks for confirmation of each item with its `prompt`, then calls `delete` on it.
+    ///
+    /// Items are named by `label` in messages. `delete` returns `None` for an
+    /// item it defers, whose outcome is then passed to [`Deleter::record`] later.
+    pub fn run<'t, T: 't, E: fmt::Display>(
+        &mut self,
+        items: impl IntoIterator<Item = &'t T>,
+        label: impl Fn(&T) -> String,
+        prompt: impl Fn(&T) -> String,
+        mut delete: impl FnMut(&'t T) -> Option<Result<(), E>>,
+    ) {
+        for item in items {
+            if !io_utils::confirm(&prompt(item), self.yes) {
+                self.inc();
+                continue;
+            }
+
+            let label = label(item);
+            if self.yes {
+                self.set_message(format!("{} {}...", self.verb.forms().2, label));
+            }
+            if let Some(result) = delete(item) {
+                self.record(&label, result);
+            }
+        }
+    }
+
+    /// Counts the outcome of one deletion, reporting failures.
+    pub fn record<E: fmt::Display>(&mut self, label: &str, result: Result<(), E>) {
+        let (verb, past, _) = self.verb.forms();
+        match result {
+            Ok(()) => {
+                self.summary.deleted += 1;
+                debug!("{} {}", past, label);
+            }
+            Err(e) => {
+                self.summary.failed += 1;
+                error!("Failed to {} {}: {}", verb, label, e);
+                if !self.verbose {
+                    io_utils::print_error(self.progress, &format!("Failed to {} {}: {}", verb, label, e).red());
+                }
+            }
+        }
+        self.inc();
+    }
+
+    /// Shows a message on the progress bar.
+    pub fn set_message(&self, message: String) {
+        if let Some(progress) = self.progress {
+            progress.set_message(message);
+        }
+    }
+
+    fn inc(&self) {
+        if let Some(progress) = self.progress {
+            progress.inc(1);
+        }
+    }
+
+    /// Runs every deletion with [`Deleter::run`], then finishes.
+    pub fn run_all<'t, T: 't, E: fmt::Display>(
+        mut self,
+        items: impl IntoIterator<Item = &'t T>,
+        label: impl Fn(&T) -> String,
+        prompt: impl Fn(&T) -> String,
+        delete: impl FnMut(&'t T) -> Option<Result<(), E>>,
+    ) -> DeleteSummary {
+        self.run(items, label, prompt, delete);
+        self.finish()
+    }
+
+    /// Clears the progress bar and returns how many deletions succeeded and failed.
+    pub fn finish(self) -> DeleteSummary {
+        if let Some(progress) = self.progress {
+            progress.finish_and_clear();
+        }
+        self.summary
+    }
+}
+
+/// Scans every local and remote branch and returns the ones matching the selection.
+///
+/// Protected branches are included with the reason they are protected, so they can
+/// be shown as skipped.
+pub fn scan_branches(repo: &Repository, selection: &Selection) -> Result<Vec<BranchDetails>, Box<dyn std::error::Error>> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let mut patch_ids = HashMap::new();
+
+    let mut branches = Vec::new();
+    for branch_result in repo.branches(None)? {
+        let (branch, branch_type) = branch_result?;
+
+        let name = branch.name()?.unwrap_or("<invalid UTF-8>");
+        let kind = match branch_type {
+            BranchType::Local => "local",
+            BranchType::Remote => "remote",
+        };
+
+        let commit = branch.get().target().and_then(|oid| repo.find_commit(oid).ok());
+
+        let remote = match branch_type {
+            BranchType::Local => None,
+            BranchType::Remote => branch.get().name()
+                .and_then(|r| repo.branch_remote_name(r).ok())
+                .and_then(|buf| buf.as_str().map(str::to_string)),
+        };
+        if remote.as_ref().is_some_and(|r| !selection.remotes.contains(r)) {
+            continue;
+        }
+
+        if let Some(ref commit) = commit && selection.author.as_ref().is_some_and(|a| !a.matches(&commit.author())) {
+            continue;
+        }
+
+        if let Some(commit) = commit {
+            let branch_time = branch_time(repo, &branch, &commit, selection)?;
+            let age = Duration::from_secs(now.saturating_sub(branch_time as u64)).as_secs() / 86400;
+
+            let mut reason = match selection.base {
+                Some((ref base_name, base_oid)) => {
+                    if is_base_branch(name, remote.as_deref(), base_name) {
+                        continue;
+                    }
+                    match git_utils::merge_status(repo, commit.id(), base_oid, &mut patch_ids)? {
+                        MergeStatus::Merged => Some(format!("merged into {}", base_name)),
+                        MergeStatus::SquashMerged => Some(format!("squash-merged into {}", base_name)),
+                        MergeStatus::Unmerged => None,
+                    }
+                }
+                None if !selection.gone && selection.age.matches(branch_time, now) => Some("stale".to_string()),
+                None => None,
+            };
+
+            // Branches whose upstream was deleted are selected regardless of age
+            if reason.is_none() && selection.gone && branch_type == BranchType::Local && git_utils::upstream_gone(repo, &branch) {
+                reason = Some("upstream gone".to_string());
+            }
+
+            if let Some(reason) = reason {
+                let skipped = selection.protection.check(name, remote.as_deref());
+                if let Some(ref why) = skipped {
+                    debug!("Skipping protected branch {} ({}).", name, why);
+                }
+                let (upstream, ahead, behind) = upstream_status(repo, &branch, &commit);
+                let base_counts = selection.mainline
+                    .as_ref()
+                    .and_then(|(_, oid)| repo.graph_ahead_behind(commit.id(), *oid).ok());
+                branches.push(BranchDetails {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                    remote,
+                    age,
+                    tip: commit.id().to_string(),
+                    reason,
+                    skipped,
+                    author: commit.author().to_string(),
+                    last_commit_date: format_time(&commit.time()),
+                    upstream,
+                    ahead,
+                    behind,
+                    committer: commit.committer().to_string(),
+                    base: selection.mainline.as_ref().map(|(name, _)| name.clone()),
+                    base_ahead: base_counts.map(|c| c.0),
+                    base_behind: base_counts.map(|c| c.1),
+                    unpushed: None,
+                });
+            }
+        }
+
+        debug!("Found {}:{} branch.", kind, name);
+    }
+
+    // Group local branches first, then remote branches by remote
+    branches.sort_by(|a, b| a.remote.cmp(&b.remote).then(b.age.cmp(&a.age)));
+    Ok(branches)
+}
+
+/// Returns whether a branch is the base branch itself, locally or on its remote.
+fn is_base_branch(name: &str, remote: Option<&str>, base_name: &str) -> bool {
+    name == base_name || remote.and_then(|r| name.strip_prefix(r)?.strip_prefix('/')) == Some(base_name)
+}
+
+/// Marks local branches with commits that exist on no other branch or tag as skipped.
+///
+/// Branches that are about to be deleted along with it do not count, so a stale
+/// branch is not considered pushed just because its stale remote-tracking branch
+/// is selected too. The number of such commits is recorded either way. With
+/// `force`, the branches are still offered for deletion.
+pub fn check_unpushed(repo: &Repository, branches: &mut [BranchDetails], force: bool) -> Result<(), git2::Error> {
+    let deleted: HashSet<String> = branches
+        .iter()
+        .filter(|b| b.skipped.is_none())
+        .map(|b| match b.kind.as_str() {
+            "local" => format!("refs/heads/{}", b.name),
+            _ => format!("refs/remotes/{}", b.name),
+        })
+        .collect();
+
+    for branch in branches.iter_mut().filter(|b| b.kind == "local" && b.skipped.is_none()) {
+        let count = git_utils::unique_commits(repo, &deleted, Oid::from_str(&branch.tip)?)?;
+        branch.unpushed = Some(count);
+
+        if count > 0 && !force {
+            debug!("Skipping {} with {} unpushed commits.", branch.name, count);
+            branch.skipped = Some(format!("{} unpushed commits", count));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the Unix time that defines the age of a branch.
+///
+/// Falls back to the committer date of the tip when the branch has no reflog
+/// or no commits of its own.
+fn branch_time(repo: &Repository, branch: &Branch, commit: &Commit, selection: &Selection) -> Result<i64, git2::Error> {
+    let time = match selection.age_by {
+        AgeBy::Committer => None,
+        AgeBy::Author => Some(commit.author().when().seconds()),
+        AgeBy::Reflog => match branch.get().name() {
+            Some(refname) => repo.reflog(refname)?.get(0).map(|entry| entry.committer().when().seconds()),
+            None => None,
+        },
+        AgeBy::FirstCommit => match selection.mainline {
+            Some((_, base_oid)) => {
+                let mut revwalk = repo.revwalk()?;
+                revwalk.push(commit.id())?;
+                revwalk.hide(base_oid)?;
+                match revwalk.last() {
+                    Some(oid) => Some(repo.find_commit(oid?)?.time().seconds()),
+                    None => None,
+                }
+            }
+            None => None,
+        },
+    };
+    Ok(time.unwrap_or_else(|| commit.time().seconds()))
+}
+
+/// Returns the upstream of a local branch and how far the branch is ahead and behind it.
+fn upstream_status(repo: &Repository, branch: &Branch, commit: &Commit) -> (Option<String>, Option<usize>, Option<usize>) {
+    let Ok(upstream) = branch.upstream() else {
+        return (None, None, None);
+    };
+    let name = upstream.name().ok().flatten().map(str::to_string);
+    let counts = upstream.get().target().and_then(|oid| repo.graph_ahead_behind(commit.id(), oid).ok());
+    (name, counts.map(|c| c.0), counts.map(|c| c.1))
+}
+
+/// Formats a commit time as an RFC 3339 timestamp in UTC.
+fn format_time(time: &git2::Time) -> String {
+    DateTime::<Utc>::from_timestamp(time.seconds(), 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Deletes the given branches, asking for confirmation of each one unless `yes` is set.
+///
+/// Skipped branches are left alone. Each branch is backed up to the trash before
+/// it is deleted. Local branches are deleted as they are confirmed, and remote
+/// branches are then deleted with a single push per remote. A failure to delete
+/// one branch, including a rejection by the remote, is reported and does not
+/// stop the remaining deletions.
+pub fn delete_branches(repo: &Repository, branches: &[BranchDetails], yes: bool, progress: Option<&ProgressBar>, verbose: bool) -> DeleteSummary {
+    let trash = Trash::open(repo);
+    let mut deleter = Deleter::new(Verb::Delete, yes, progress, verbose);
+
+    // Remote branches are deleted after confirmation with one push per remote
+    let mut remote_batches: Vec<(&str, Vec<(&BranchDetails, TrashEntry)>)> = Vec::new();
+
+    let prompt = |branch: &BranchDetails| format!("Delete branch {}?", branch.name);
+    deleter.run(branches.iter().filter(|b| b.skipped.is_none()), branch_label, prompt, |branch| {
+        let recorded = trash.record(repo, &branch.name, &branch.kind, branch.remote.as_deref(), &branch.tip);
+        match (recorded, branch.remote.as_deref()) {
+            (Ok(entry), Some(remote)) if branch.kind != "local" => {
+                match remote_batches.iter_mut().find(|(name, _)| *name == remote) {
+                    Some((_, batch)) => batch.push((branch, entry)),
+                    None => remote_batches.push((remote, vec![(branch, entry)])),
+                }
+                None
+            }
+            (recorded, _) => Some(recorded.and_then(|entry| {
+                let deleted = git_utils::delete_local_branch(repo, &branch.name);
+                // A branch that was not deleted needs no backup
+                if deleted.is_err() {
+                    trash.remove(repo, &[entry])?;
+                }
+                Ok(deleted?)
+            })),
+        }
+    });
+
+    for (remote, batch) in remote_batches {
+        deleter.set_message(format!("Deleting {} branches on {}...", batch.len(), remote));
+
+        let names: Vec<&str> = batch
+            .iter()
+            .map(|(branch, _)| branch.name.strip_prefix(remote).and_then(|n| n.strip_prefix('/')).unwrap_or(&branch.name))
+            .collect();
+        let rejected = git_utils::delete_remote_branches(repo, remote, &names, progress);
+
+        for ((branch, entry), name) in batch.into_iter().zip(names) {
+            let result: Result<(), Box<dyn std::error::Error>> = match rejected {
+                Ok(ref rejected) => match rejected.get(name) {
+                    Some(status) => Err(format!("rejected by {}: {}", remote, status).into()),
+                    None => Ok(()),
+                },
+                Err(ref e) => Err(e.message().into()),
+            };
+            // A branch that was not deleted needs no backup
+            let result = match result {
+                Err(e) => trash.remove(repo, &[entry]).and(Err(e)),
+                ok => ok,
+            };
+            deleter.record(&branch_label(branch), result);
+        }
+    }
+
+    deleter.finish()
+}
+
+/// Names a branch in messages, e.g. `remote branch origin/old`.
+fn branch_label(branch: &BranchDetails) -> String {
+    format!("{} branch {}", branch.kind, branch.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_base_branch_matches_local_and_remote_base() {
+        assert!(is_base_branch("main", None, "main"));
+        assert!(is_base_branch("origin/main", Some("origin"), "main"));
+        assert!(is_base_branch("upstream/main", Some("upstream"), "main"));
+    }
+
+    #[test]
+    fn is_base_branch_ignores_other_branches() {
+        assert!(!is_base_branch("fix/main", None, "main"));
+        assert!(!is_base_branch("user/main", None, "main"));
+        assert!(!is_base_branch("origin/fix/main", Some("origin"), "main"));
+        assert!(!is_base_branch("origin/main", Some("upstream"), "main"));
+        assert!(!is_base_branch("mainline", None, "main"));
+    }
+}
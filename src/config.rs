@@ -0,0 +1,212 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::debug;
+use serde::Deserialize;
+use git2::Repository;
+use crate::time_utils;
+
+/// Name of the repository-level config file, relative to the working tree root.
+const REPO_CONFIG_FILE: &str = ".purgit.toml";
+
+/// Options that can be set in a purgit config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    stale: Option<DurationValue>,
+    remote: Option<String>,
+    format: Option<String>,
+    timeout: Option<DurationValue>,
+    #[serde(default)]
+    protect: Vec<String>,
+}
+
+/// A duration given either as a number (days for `stale`, seconds for
+/// `timeout`) or as a duration string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DurationValue {
+    Number(u64),
+    Text(String),
+}
+
+/// Where a config value was taken from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    GitConfig(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::GitConfig(key) => write!(f, "git config {}", key),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// A config value along with where it came from.
+#[derive(Debug, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    fn new(value: T, source: Source) -> Self {
+        Setting { value, source }
+    }
+
+    /// Overrides the value with one given on the command line, if any.
+    pub fn override_with(&mut self, value: Option<T>) {
+        if let Some(value) = value {
+            *self = Setting::new(value, Source::Cli);
+        }
+    }
+}
+
+/// The merged purgit configuration.
+///
+/// Values are layered in increasing order of precedence: built-in defaults,
+/// the global config file, the repository's `.purgit.toml`, `purgit.*` keys
+/// in git config, and finally command line flags. Protected patterns are
+/// collected from every layer rather than overridden.
+#[derive(Debug)]
+pub struct Config {
+    pub stale: Setting<Duration>,
+    pub remote: Setting<String>,
+    pub format: Setting<String>,
+    pub timeout: Setting<Duration>,
+    pub protect: Vec<Setting<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            stale: Setting::new(Duration::from_secs(30 * 86400), Source::Default),
+            remote: Setting::new("origin".to_string(), Source::Default),
+            format: Setting::new("text".to_string(), Source::Default),
+            timeout: Setting::new(Duration::from_secs(60), Source::Default),
+            protect: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the layered configuration, including repository-level settings if a repository is given.
+    pub fn load(repo: Option<&Repository>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = Config::default();
+
+        if let Some(path) = global_config_path() {
+            config.apply_file(&path)?;
+        }
+
+        if let Some(repo) = repo {
+            if let Some(workdir) = repo.workdir() {
+                config.apply_file(&workdir.join(REPO_CONFIG_FILE))?;
+            }
+            config.apply_git_config(repo)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the protected patterns without their sources.
+    pub fn protect_patterns(&self) -> Vec<String> {
+        self.protect.iter().map(|p| p.value.clone()).collect()
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        debug!("Reading config from {}.", path.display());
+        let contents = fs::read_to_string(path)?;
+        let file: ConfigFile = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        let source = Source::File(path.to_path_buf());
+
+        if let Some(stale) = file.stale {
+            let stale = match stale {
+                DurationValue::Number(days) => days
+                    .checked_mul(86400)
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| format!("Invalid config file {}: stale of {} days is too long", path.display(), days))?,
+                DurationValue::Text(duration) => time_utils::parse_duration(&duration)
+                    .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+            };
+            self.stale = Setting::new(stale, source.clone());
+        }
+        if let Some(remote) = file.remote {
+            self.remote = Setting::new(remote, source.clone());
+        }
+        if let Some(format) = file.format {
+            self.format = Setting::new(format, source.clone());
+        }
+        if let Some(timeout) = file.timeout {
+            let timeout = match timeout {
+                DurationValue::Number(secs) => Duration::from_secs(secs),
+                DurationValue::Text(duration) => parse_timeout(&duration)
+                    .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+            };
+            self.timeout = Setting::new(timeout, source.clone());
+        }
+        self.protect.extend(file.protect.into_iter().map(|p| Setting::new(p, source.clone())));
+        Ok(())
+    }
+
+    fn apply_git_config(&mut self, repo: &Repository) -> Result<(), Box<dyn std::error::Error>> {
+        let git_config = repo.config()?;
+
+        if let Ok(stale) = git_config.get_string("purgit.stale") {
+            let stale = time_utils::parse_duration(&stale).map_err(|e| format!("Invalid purgit.stale: {}", e))?;
+            self.stale = Setting::new(stale, Source::GitConfig("purgit.stale".to_string()));
+        }
+        if let Ok(remote) = git_config.get_string("purgit.remote") {
+            self.remote = Setting::new(remote, Source::GitConfig("purgit.remote".to_string()));
+        }
+        if let Ok(format) = git_config.get_string("purgit.format") {
+            self.format = Setting::new(format, Source::GitConfig("purgit.format".to_string()));
+        }
+
+        if let Ok(timeout) = git_config.get_string("purgit.timeout") {
+            let timeout = parse_timeout(&timeout).map_err(|e| format!("Invalid purgit.timeout: {}", e))?;
+            self.timeout = Setting::new(timeout, Source::GitConfig("purgit.timeout".to_string()));
+        }
+
+        let mut entries = git_config.multivar("purgit.protect", None)?;
+        while let Some(entry) = entries.next() {
+            if let Some(value) = entry?.value() {
+                self.protect.push(Setting::new(value.to_string(), Source::GitConfig("purgit.protect".to_string())));
+            }
+        }
+
+        debug!("Read purgit settings from git config.");
+        Ok(())
+    }
+}
+
+/// Parses a network timeout, where a number without a unit is a number of seconds.
+pub fn parse_timeout(input: &str) -> Result<Duration, String> {
+    match input.trim().parse::<u64>() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => time_utils::parse_duration(input),
+    }
+}
+
+/// Returns the path of the global config file, honouring `XDG_CONFIG_HOME`.
+fn global_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::home_dir().map(|home| home.join(".config")))?;
+
+    Some(config_home.join("purgit").join("config.toml"))
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use git2::{ErrorClass, ErrorCode};
+
+/// An error ending a purgit run, each kind with its own exit code.
+///
+/// Exit code 2 is left to command line usage errors.
+#[derive(Debug)]
+pub enum Error {
+    /// No repository could be found or opened.
+    NoRepository(String),
+    /// A remote could not be reached or refused the credentials.
+    Fetch(String),
+    /// A reference is locked by another git process.
+    RefLocked(String),
+    /// Protected branches were asked to be deleted and were kept.
+    Protected(usize),
+    /// Some of the requested deletions failed, e.g. `delete` of `branches`.
+    PartialFailure { failed: usize, total: usize, action: &'static str, items: &'static str },
+    /// The user declined to go ahead.
+    Aborted,
+    /// Stale branches were found with `--fail-on-stale`.
+    StaleBranches(usize),
+    /// Any other error.
+    Other(Box<dyn std::error::Error>),
+}
+
+impl Error {
+    /// Returns the process exit code for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Other(_) => 1,
+            Error::NoRepository(_) => 3,
+            Error::Fetch(_) => 4,
+            Error::RefLocked(_) => 5,
+            Error::Protected(_) => 6,
+            Error::PartialFailure { .. } => 7,
+            Error::Aborted => 8,
+            Error::StaleBranches(_) => 9,
+        }
+    }
+
+    /// Returns a partial failure error if any of the `total` attempts failed.
+    pub fn check_failed(failed: usize, total: usize, action: &'static str, items: &'static str) -> Result<(), Error> {
+        if failed > 0 {
+            return Err(Error::PartialFailure { failed, total, action, items });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoRepository(message) => write!(f, "{}", message),
+            Error::Fetch(message) => write!(f, "Could not reach the remote: {}", message),
+            Error::RefLocked(message) => write!(f, "A reference is locked by another git process: {}", message),
+            Error::Protected(count) => write!(f, "{} protected branches were not deleted", count),
+            Error::PartialFailure { failed, total, action, items } => write!(f, "Failed to {} {} of {} {}", action, failed, total, items),
+            Error::Aborted => write!(f, "Cancelled, nothing was deleted"),
+            Error::StaleBranches(count) => write!(f, "Found {} branches to clean up", count),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<git2::Error> for Error {
+    fn from(e: git2::Error) -> Self {
+        match (e.code(), e.class()) {
+            (ErrorCode::Locked, _) => Error::RefLocked(e.message().to_string()),
+            (ErrorCode::Auth | ErrorCode::Certificate, _) | (_, ErrorClass::Net | ErrorClass::Ssh | ErrorClass::Http | ErrorClass::Ssl) => {
+                Error::Fetch(e.message().to_string())
+            }
+            (_, ErrorClass::Repository) if e.code() == ErrorCode::NotFound => Error::NoRepository(e.message().to_string()),
+            _ => Error::Other(Box::new(e)),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        // Errors passed up from helpers keep their kind
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        match e.downcast::<git2::Error>() {
+            Ok(e) => (*e).into(),
+            Err(e) => Error::Other(e),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message.into())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.into())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Other(Box::new(e))
+    }
+}
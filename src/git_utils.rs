@@ -1,18 +1,45 @@
-use std::path::PathBuf;
-use log::debug;
-use git2::{Repository, RemoteCallbacks, FetchOptions, Cred, AutotagOption};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use log::{debug, info};
+use indicatif::{HumanBytes, ProgressBar};
+use git2::{Branch, Repository, RemoteCallbacks, FetchOptions, PushOptions, AutotagOption, BranchType, Direction, Oid, Sort};
+use crate::auth::Authenticator;
+
+/// Network timeout in milliseconds, or 0 for none.
+static NETWORK_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+/// How a branch tip relates to a base branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStatus {
+    /// The branch has commits that are not in the base branch.
+    Unmerged,
+    /// The branch tip is reachable from the base branch.
+    Merged,
+    /// The branch changes were applied to the base branch as a single squashed commit.
+    SquashMerged,
+}
 
 /// Resolve the Git repository name using its file path.
+///
+/// Bare repositories are named after their directory without the `.git`
+/// suffix, and linked worktrees after the repository they belong to.
 pub fn resolve_name(repo: &Repository) -> Result<String, Box<dyn std::error::Error>> {
-    let repo_path: PathBuf = repo.path().canonicalize()?;
+    let repo_path: PathBuf = repo.commondir().canonicalize()?;
+
+    let repo_root = if repo.is_bare() {
+        repo_path.file_name()
+    } else {
+        repo_path.parent().and_then(|p| p.file_name())
+    }
+    .ok_or("Could not determine repository name")?
+    .to_string_lossy();
 
-    let repo_root = repo_path
-        .parent()
-        .and_then(|p| p.file_name())
-        .ok_or("Could not determine repository name")?;
-    
     debug!("Resolved repository name.");
-    Ok(repo_root.to_string_lossy().into_owned())
+    Ok(repo_root.strip_suffix(".git").unwrap_or(&repo_root).to_string())
 }
 
 /// Fetches all updates from the specified remote of the given Git repository.
@@ -23,20 +50,519 @@ pub fn resolve_name(repo: &Repository) -> Result<String, Box<dyn std::error::Err
 /// - Prunes any deleted remote branches
 /// - Updates `.git/FETCH_HEAD`
 ///
-/// Authentication is handled using Git's configured credential helpers.
-pub fn fetch_remote(repo: &Repository, remote_name: &str) -> Result<(), git2::Error> {
+/// Authentication tries ssh-agent, SSH keys, askpass, environment tokens and
+/// finally Git's configured credential helpers.
+///
+/// Transfer progress and server messages are shown on `progress`, and server
+/// messages are also logged in verbose mode. The fetch is aborted if nothing is
+/// received or indexed for longer than the network timeout.
+pub fn fetch_remote(repo: &Repository, remote_name: &str, progress: Option<&ProgressBar>) -> Result<(), git2::Error> {
     debug!("Setting up remote fetch options...");
     let mut remote = repo.find_remote(remote_name)?;
-    let mut callbacks = RemoteCallbacks::new();
-    callbacks.credentials(move |url, username_from_url, _| {
-        Cred::credential_helper(&repo.config()?, url, username_from_url)
+    let timeout = network_timeout();
+    let started = Instant::now();
+    let last_activity = Cell::new(started);
+    let last_counts = Cell::new((0, 0, 0, 0));
+    let stalled = Cell::new(false);
+
+    // Aborts the fetch when nothing happened for longer than the network timeout
+    let check_stalled = || {
+        if timeout.is_some_and(|timeout| last_activity.get().elapsed() > timeout) {
+            stalled.set(true);
+            return false;
+        }
+        true
+    };
+
+    let mut callbacks = remote_callbacks(repo, progress);
+    callbacks.transfer_progress(|stats| {
+        // Indexing and resolving deltas count as progress even when no bytes arrive
+        let counts = (stats.received_bytes(), stats.received_objects(), stats.indexed_objects(), stats.indexed_deltas());
+        if counts != last_counts.get() {
+            last_counts.set(counts);
+            last_activity.set(Instant::now());
+        }
+        if let Some(progress) = progress {
+            progress.set_message(format_transfer(remote_name, &stats, started.elapsed()));
+        }
+        check_stalled()
     });
+    callbacks.sideband_progress(|data| {
+        last_activity.set(Instant::now());
+        // Servers redraw their progress lines with carriage returns
+        let text = String::from_utf8_lossy(data);
+        for line in text.split(['\r', '\n']).map(str::trim).filter(|l| !l.is_empty()) {
+            info!("remote: {}", line);
+            if let Some(progress) = progress {
+                progress.set_message(format!("Fetching {}: {}", remote_name, line));
+            }
+        }
+        check_stalled()
+    });
+
     let mut fetch_options = FetchOptions::new();
     fetch_options.remote_callbacks(callbacks);
     fetch_options.download_tags(AutotagOption::All);
     fetch_options.update_fetchhead(true);
     fetch_options.prune(git2::FetchPrune::On);
-    remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
-    debug!("Fetched from remote.");
+    let result = remote.fetch(&[] as &[&str], Some(&mut fetch_options), None);
+    drop(fetch_options);
+
+    if stalled.get() {
+        let timeout = timeout.map(|t| t.as_secs()).unwrap_or_default();
+        return Err(git2::Error::new(git2::ErrorCode::GenericError, git2::ErrorClass::Net, format!("Fetch stalled, nothing received for {} seconds", timeout)));
+    }
+    result?;
+    debug!("Fetched from remote in {:.1}s.", started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Formats fetch progress, e.g. `Fetching origin: 120/300 objects, 1.2 MiB at 600 KiB/s`.
+fn format_transfer(remote_name: &str, stats: &git2::Progress, elapsed: Duration) -> String {
+    if stats.received_objects() == stats.total_objects() && stats.total_deltas() > 0 {
+        return format!("Fetching {}: resolving deltas {}/{}", remote_name, stats.indexed_deltas(), stats.total_deltas());
+    }
+
+    let rate = stats.received_bytes() as f64 / elapsed.as_secs_f64().max(0.001);
+    format!(
+        "Fetching {}: {}/{} objects, {} at {}/s",
+        remote_name,
+        stats.received_objects(),
+        stats.total_objects(),
+        HumanBytes(stats.received_bytes() as u64),
+        HumanBytes(rate as u64),
+    )
+}
+
+/// Sets how long network operations may go without any data before they are aborted.
+///
+/// This applies to every remote for the rest of the run, and must be called
+/// before any fetch or push starts.
+pub fn set_network_timeout(timeout: Duration) -> Result<(), git2::Error> {
+    let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: libgit2 global options are only set from the main thread before
+    // any network operation starts.
+    unsafe {
+        git2::opts::set_server_connect_timeout_in_milliseconds(millis)?;
+        git2::opts::set_server_timeout_in_milliseconds(millis)?;
+    }
+    NETWORK_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    debug!("Network timeout set to {}s.", timeout.as_secs());
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Returns the network timeout, or `None` if none was set.
+fn network_timeout() -> Option<Duration> {
+    match NETWORK_TIMEOUT_MS.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+/// Returns how long ago the repository was last fetched, based on the
+/// modification time of `.git/FETCH_HEAD`.
+///
+/// Returns `None` if the repository has never been fetched.
+pub fn last_fetch_age(repo: &Repository) -> Option<Duration> {
+    let modified = fs::metadata(repo.path().join("FETCH_HEAD")).ok()?.modified().ok()?;
+    Some(SystemTime::now().duration_since(modified).unwrap_or_default())
+}
+
+/// Checks whether a local branch has an upstream configured that no longer exists,
+/// e.g. because the remote branch was deleted and pruned.
+pub fn upstream_gone(repo: &Repository, branch: &Branch) -> bool {
+    if branch.upstream().is_ok() {
+        return false;
+    }
+
+    let Some(refname) = branch.get().name() else {
+        return false;
+    };
+    match repo.branch_upstream_name(refname) {
+        Ok(upstream) => {
+            let gone = upstream.as_str().is_some_and(|u| repo.find_reference(u).is_err());
+            if gone {
+                debug!("Upstream of {} is gone.", refname);
+            }
+            gone
+        }
+        Err(_) => false,
+    }
+}
+
+/// Counts the commits reachable from `tip` that are not reachable from any
+/// branch, remote-tracking branch or tag other than those in `excluded`.
+pub fn unique_commits(repo: &Repository, excluded: &HashSet<String>, tip: Oid) -> Result<usize, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(tip)?;
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        let shared = name.starts_with("refs/heads/") || name.starts_with("refs/remotes/") || name.starts_with("refs/tags/");
+        if !shared || excluded.contains(name) {
+            continue;
+        }
+        if let Ok(commit) = reference.peel_to_commit() {
+            revwalk.hide(commit.id())?;
+        }
+    }
+
+    let mut count = 0;
+    for oid in revwalk {
+        oid?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Deletes the given local branch.
+pub fn delete_local_branch(repo: &Repository, branch_name: &str) -> Result<(), git2::Error> {
+    let mut branch = repo.find_branch(branch_name, BranchType::Local)?;
+    branch.delete()?;
+    debug!("Deleted local branch {}.", branch_name);
+    Ok(())
+}
+
+/// Deletes the given local tag.
+pub fn delete_local_tag(repo: &Repository, tag_name: &str) -> Result<(), git2::Error> {
+    repo.tag_delete(tag_name)?;
+    debug!("Deleted local tag {}.", tag_name);
+    Ok(())
+}
+
+/// Deletes several branches from the named remote in a single push.
+///
+/// Each branch is given by its name on the remote (e.g. `feature`) and deleted
+/// by pushing an empty source refspec (`:refs/heads/<name>`). The push goes
+/// through even if the server rejects some of the references, e.g. because
+/// they are protected. Returns the rejection reason of every branch that was
+/// not deleted; the remote-tracking branches of the others are removed.
+pub fn delete_remote_branches(repo: &Repository, remote_name: &str, branch_names: &[&str], progress: Option<&ProgressBar>) -> Result<HashMap<String, String>, git2::Error> {
+    debug!("Pushing deletion of {} branches to {}...", branch_names.len(), remote_name);
+    let refspecs: Vec<String> = branch_names.iter().map(|name| format!(":refs/heads/{}", name)).collect();
+    let rejected = push_with_status(repo, remote_name, &refspecs, progress)?;
+
+    let rejected: HashMap<String, String> = rejected
+        .into_iter()
+        .map(|(refname, status)| (refname.strip_prefix("refs/heads/").unwrap_or(&refname).to_string(), status))
+        .collect();
+    debug!("Deleted {} remote branches, {} rejected.", branch_names.len() - rejected.len(), rejected.len());
+    Ok(rejected)
+}
+
+/// Creates the given remote branch (e.g. `origin/feature`) on its remote by
+/// pushing the local reference `source` to it.
+pub fn push_remote_branch(repo: &Repository, branch_name: &str, source: &str) -> Result<(), git2::Error> {
+    let (remote_name, remote_branch) = split_remote_branch(repo, branch_name)?;
+    debug!("Pushing {} to {} on {}...", source, remote_branch, remote_name);
+    push(repo, &remote_name, &[format!("{}:refs/heads/{}", source, remote_branch)], None)?;
+    debug!("Pushed remote branch {}.", branch_name);
+    Ok(())
+}
+
+/// Lists the branches checked out in linked worktrees, along with which worktree has them.
+///
+/// When run from a linked worktree, the branch checked out in the main
+/// worktree is included too.
+pub fn checked_out_branches(repo: &Repository) -> Result<Vec<(String, String)>, git2::Error> {
+    let mut branches = Vec::new();
+    for name in repo.worktrees()?.iter().flatten() {
+        if let Some(branch) = worktree_branch(repo, name) {
+            branches.push((branch, format!("worktree {}", name)));
+        }
+    }
+
+    if repo.is_worktree()
+        && let Some(branch) = read_head_branch(&repo.commondir().join("HEAD"))
+    {
+        branches.push((branch, "main worktree".to_string()));
+    }
+
+    debug!("Found {} branches checked out in other worktrees.", branches.len());
+    Ok(branches)
+}
+
+/// Returns the branch checked out in the named linked worktree, if any.
+///
+/// The branch is read from the worktree's administrative directory, so it is
+/// known even when the worktree directory itself is missing.
+pub fn worktree_branch(repo: &Repository, worktree_name: &str) -> Option<String> {
+    read_head_branch(&repo.commondir().join("worktrees").join(worktree_name).join("HEAD"))
+}
+
+/// Reads the branch name from a `HEAD` file, or `None` if HEAD is detached.
+fn read_head_branch(path: &Path) -> Option<String> {
+    let head = fs::read_to_string(path).ok()?;
+    head.trim().strip_prefix("ref: refs/heads/").map(str::to_string)
+}
+
+/// Lists the tags on the given remote by name, with the object each points to.
+pub fn list_remote_tags(repo: &Repository, remote_name: &str, progress: Option<&ProgressBar>) -> Result<HashMap<String, Oid>, git2::Error> {
+    debug!("Listing tags on {}...", remote_name);
+    let mut remote = repo.find_remote(remote_name)?;
+    let connection = remote.connect_auth(Direction::Fetch, Some(remote_callbacks(repo, progress)), None)?;
+
+    let mut tags = HashMap::new();
+    for head in connection.list()? {
+        // Peeled entries (`refs/tags/v1^{}`) point at the tagged commit rather than the tag
+        if let Some(tag) = head.name().strip_prefix("refs/tags/") && !tag.ends_with("^{}") {
+            tags.insert(tag.to_string(), head.oid());
+        }
+    }
+
+    debug!("Found {} tags on {}.", tags.len(), remote_name);
+    Ok(tags)
+}
+
+/// Deletes the given tag from the named remote.
+pub fn delete_remote_tag(repo: &Repository, remote_name: &str, tag_name: &str, progress: Option<&ProgressBar>) -> Result<(), git2::Error> {
+    debug!("Pushing deletion of tag {} to {}...", tag_name, remote_name);
+    push(repo, remote_name, &[format!(":refs/tags/{}", tag_name)], progress)?;
+    debug!("Deleted remote tag {}.", tag_name);
+    Ok(())
+}
+
+/// Creates the given tag on the named remote by pushing the local reference `source` to it.
+pub fn push_remote_tag(repo: &Repository, remote_name: &str, tag_name: &str, source: &str) -> Result<(), git2::Error> {
+    debug!("Pushing {} to tag {} on {}...", source, tag_name, remote_name);
+    push(repo, remote_name, &[format!("{}:refs/tags/{}", source, tag_name)], None)?;
+    debug!("Pushed remote tag {}.", tag_name);
+    Ok(())
+}
+
+/// Splits a remote branch name such as `origin/feature` into the remote name
+/// and the branch name on that remote.
+fn split_remote_branch(repo: &Repository, branch_name: &str) -> Result<(String, String), git2::Error> {
+    let tracking_ref = format!("refs/remotes/{}", branch_name);
+    let remote_buf = repo.branch_remote_name(&tracking_ref)?;
+    let remote_name = remote_buf.as_str().ok_or_else(|| git2::Error::from_str("Remote name is not valid UTF-8"))?;
+    let remote_branch = branch_name
+        .strip_prefix(remote_name)
+        .and_then(|b| b.strip_prefix('/'))
+        .ok_or_else(|| git2::Error::from_str("Could not determine remote branch name"))?;
+    Ok((remote_name.to_string(), remote_branch.to_string()))
+}
+
+/// Pushes the given refspecs to the named remote.
+///
+/// Authentication goes through the same credential chain as fetching. An
+/// error is returned if the remote rejects any of the references.
+fn push(repo: &Repository, remote_name: &str, refspecs: &[String], progress: Option<&ProgressBar>) -> Result<(), git2::Error> {
+    if let Some((_, status)) = push_with_status(repo, remote_name, refspecs, progress)?.into_iter().next() {
+        return Err(git2::Error::from_str(&format!("Remote rejected push: {}", status)));
+    }
+    Ok(())
+}
+
+/// Pushes the given refspecs to the named remote in a single push.
+///
+/// Returns the status message of every remote reference the server rejected,
+/// as reported through `push_update_reference`.
+fn push_with_status(repo: &Repository, remote_name: &str, refspecs: &[String], progress: Option<&ProgressBar>) -> Result<HashMap<String, String>, git2::Error> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let mut rejected = HashMap::new();
+    {
+        let mut callbacks = remote_callbacks(repo, progress);
+        callbacks.push_update_reference(|refname, status| {
+            match status {
+                Some(status) => {
+                    debug!("{} rejected {}: {}", remote_name, refname, status);
+                    rejected.insert(refname.to_string(), status.to_string());
+                }
+                None => debug!("{} accepted {}.", remote_name, refname),
+            }
+            Ok(())
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+        remote.push(refspecs, Some(&mut push_options))?;
+    }
+    Ok(rejected)
+}
+
+/// Resolves the base branch used for merged-branch detection.
+///
+/// If no base is given, the first of `main` and `master` that exists locally
+/// (or on the given remote) is used. Returns the base name and its tip commit.
+pub fn resolve_base(repo: &Repository, base: Option<&str>, remote: &str) -> Result<(String, Oid), git2::Error> {
+    if let Some(base) = base {
+        let commit = repo.revparse_single(base)?.peel_to_commit()?;
+        return Ok((base.to_string(), commit.id()));
+    }
+
+    let remote_main = format!("{}/main", remote);
+    let remote_master = format!("{}/master", remote);
+    for candidate in ["main", "master", &remote_main, &remote_master] {
+        if let Ok(object) = repo.revparse_single(candidate) {
+            let commit = object.peel_to_commit()?;
+            debug!("Using {} as base branch.", candidate);
+            return Ok((candidate.to_string(), commit.id()));
+        }
+    }
+
+    Err(git2::Error::from_str("Could not find a main or master branch, please specify a base branch"))
+}
+
+/// Determines whether the commit `tip` has been merged into `base`.
+///
+/// A branch is considered merged if its tip is reachable from the base. Otherwise
+/// the combined changes since the merge base are compared by patch-id against each
+/// commit on the base, which detects branches that were squash-merged or rebased.
+/// Patch-ids of base commits are memoized in `patch_ids` across calls.
+pub fn merge_status(repo: &Repository, tip: Oid, base: Oid, patch_ids: &mut HashMap<Oid, Oid>) -> Result<MergeStatus, git2::Error> {
+    if tip == base || repo.graph_descendant_of(base, tip)? {
+        return Ok(MergeStatus::Merged);
+    }
+
+    let merge_base = match repo.merge_base(base, tip) {
+        Ok(oid) => oid,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(MergeStatus::Unmerged),
+        Err(e) => return Err(e),
+    };
+
+    let merge_base_tree = repo.find_commit(merge_base)?.tree()?;
+    let tip_tree = repo.find_commit(tip)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&merge_base_tree), Some(&tip_tree), None)?;
+    if diff.deltas().len() == 0 {
+        return Ok(MergeStatus::Unmerged);
+    }
+    let branch_patch_id = diff.patchid(None)?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+    revwalk.push(base)?;
+    revwalk.hide(merge_base)?;
+    for oid in revwalk {
+        let oid = oid?;
+        let patch_id = match patch_ids.get(&oid) {
+            Some(patch_id) => *patch_id,
+            None => {
+                let commit = repo.find_commit(oid)?;
+                if commit.parent_count() != 1 {
+                    continue;
+                }
+                let parent_tree = commit.parent(0)?.tree()?;
+                let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit.tree()?), None)?;
+                let patch_id = diff.patchid(None)?;
+                patch_ids.insert(oid, patch_id);
+                patch_id
+            }
+        };
+
+        if patch_id == branch_patch_id {
+            debug!("Found squash merge of {} in {}.", tip, oid);
+            return Ok(MergeStatus::SquashMerged);
+        }
+    }
+
+    Ok(MergeStatus::Unmerged)
+}
+
+/// Builds the remote callbacks shared by fetch and push operations.
+///
+/// Credentials come from an [`Authenticator`], which tries ssh-agent, SSH keys,
+/// askpass, environment tokens and the credential helper in turn.
+fn remote_callbacks<'a>(repo: &'a Repository, progress: Option<&'a ProgressBar>) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut authenticator = Authenticator::new(repo, progress);
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        authenticator.credentials(url, username_from_url, allowed)
+    });
+    callbacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+
+    /// Commits the given files on top of `parent` without updating any reference.
+    fn commit(repo: &Repository, parent: Option<Oid>, files: &[(&str, &str)]) -> Oid {
+        let parent = parent.map(|oid| repo.find_commit(oid).unwrap());
+        let base_tree = parent.as_ref().map(|p| p.tree().unwrap());
+        let mut builder = repo.treebuilder(base_tree.as_ref()).unwrap();
+        for (path, content) in files {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(None, &signature, &signature, "commit", &tree, &parents).unwrap()
+    }
+
+    fn init() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn merge_status_merged() {
+        let (_dir, repo) = init();
+        let root = commit(&repo, None, &[("a", "a")]);
+        let branch = commit(&repo, Some(root), &[("b", "b")]);
+        let base = commit(&repo, Some(branch), &[("c", "c")]);
+
+        let mut patch_ids = HashMap::new();
+        assert_eq!(merge_status(&repo, branch, base, &mut patch_ids).unwrap(), MergeStatus::Merged);
+        assert_eq!(merge_status(&repo, base, base, &mut patch_ids).unwrap(), MergeStatus::Merged);
+    }
+
+    #[test]
+    fn merge_status_squash_merged() {
+        let (_dir, repo) = init();
+        let root = commit(&repo, None, &[("a", "a")]);
+        let first = commit(&repo, Some(root), &[("s1", "s1")]);
+        let branch = commit(&repo, Some(first), &[("s2", "s2")]);
+        let other = commit(&repo, Some(root), &[("c", "c")]);
+        let base = commit(&repo, Some(other), &[("s1", "s1"), ("s2", "s2")]);
+
+        let mut patch_ids = HashMap::new();
+        assert_eq!(merge_status(&repo, branch, base, &mut patch_ids).unwrap(), MergeStatus::SquashMerged);
+    }
+
+    #[test]
+    fn merge_status_unmerged() {
+        let (_dir, repo) = init();
+        let root = commit(&repo, None, &[("a", "a")]);
+        let branch = commit(&repo, Some(root), &[("b", "b")]);
+        let base = commit(&repo, Some(root), &[("c", "c")]);
+        // Only part of the branch's changes made it into the base
+        let partial = commit(&repo, Some(branch), &[("d", "d")]);
+
+        let mut patch_ids = HashMap::new();
+        assert_eq!(merge_status(&repo, branch, base, &mut patch_ids).unwrap(), MergeStatus::Unmerged);
+        let base = commit(&repo, Some(base), &[("b", "b")]);
+        assert_eq!(merge_status(&repo, partial, base, &mut patch_ids).unwrap(), MergeStatus::Unmerged);
+    }
+
+    #[test]
+    fn unique_commits_ignores_excluded_refs() {
+        let (_dir, repo) = init();
+        let root = commit(&repo, None, &[("a", "a")]);
+        let tip = commit(&repo, Some(root), &[("b", "b")]);
+        repo.reference("refs/heads/main", root, false, "test").unwrap();
+        repo.reference("refs/heads/feat", tip, false, "test").unwrap();
+        repo.reference("refs/remotes/origin/feat", tip, false, "test").unwrap();
+
+        let own: HashSet<String> = ["refs/heads/feat".to_string()].into();
+        assert_eq!(unique_commits(&repo, &own, tip).unwrap(), 0);
+
+        let both: HashSet<String> = ["refs/heads/feat".to_string(), "refs/remotes/origin/feat".to_string()].into();
+        assert_eq!(unique_commits(&repo, &both, tip).unwrap(), 1);
+    }
+
+    #[test]
+    fn merge_status_unrelated_histories() {
+        let (_dir, repo) = init();
+        let branch = commit(&repo, None, &[("a", "a")]);
+        let base = commit(&repo, None, &[("b", "b")]);
+
+        let mut patch_ids = HashMap::new();
+        assert_eq!(merge_status(&repo, branch, base, &mut patch_ids).unwrap(), MergeStatus::Unmerged);
+    }
+}
@@ -1,20 +1,53 @@
 use std::io::{self, Write};
+use std::time::Duration;
+use colored::ColoredString;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// Create a spinner showing the given message, unless progress output is disabled
+pub fn spinner(disabled: bool, message: &str) -> Option<ProgressBar> {
+    if disabled {
+        return None;
+    }
+
+    Some(new_spinner(message))
+}
+
+/// Create a spinner for one of several tasks running at once, shown on its own line of `multi`
+pub fn add_spinner(multi: &MultiProgress, message: &str) -> ProgressBar {
+    multi.add(new_spinner(message))
+}
+
+fn new_spinner(message: &str) -> ProgressBar {
+    let progress = ProgressBar::new_spinner();
+    progress.set_message(message.to_string());
+    progress.enable_steady_tick(Duration::from_millis(100));
+    progress.set_style(ProgressStyle::default_spinner()
+        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
+        .template("{spinner} {msg}")
+        .expect("Invalid template"));
+    progress
+}
 
 /// Prompt the user with the given prompt and return true if they respond with "y"
+///
+/// The prompt goes to stderr so that it never mixes with a report on stdout.
 pub fn confirm(prompt: &str, default: bool) -> bool {
     if default { return true;}
-    loop {
-        print!("{prompt} (y/N): ");
-        // flush stdout so the prompt shows up immediately
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let input = input.trim().to_lowercase();
-
-        match input.as_str() {
-            "y" => return true,
-            _ => return false,
-        }
+    eprint!("{prompt} (y/N): ");
+    // flush stderr so the prompt shows up immediately
+    io::stderr().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    let input = input.trim().to_lowercase();
+
+    matches!(input.as_str(), "y")
+}
+
+/// Print a message to stderr without garbling the given progress bar
+pub fn print_error(progress: Option<&ProgressBar>, message: &ColoredString) {
+    match progress {
+        Some(progress) => progress.suspend(|| eprintln!("{}", message)),
+        None => eprintln!("{}", message),
     }
-}
\ No newline at end of file
+}
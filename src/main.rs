@@ -1,17 +1,40 @@
-use std::thread;
-use std::{io, time::SystemTime};
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Duration;
-use log::{debug, Record, Level};
+use log::{debug, warn, Record, Level};
 
 use env_logger::{Builder, Env};
 use colored::*;
-use clap::{Parser, Subcommand};
-use indicatif::{ProgressBar, ProgressStyle};
-use git2::{BranchType, Repository};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use git2::Repository;
+use indicatif::{MultiProgress, ProgressBar};
+use protect::Protection;
+use config::{Config, Setting, Source};
+use error::Error;
+use clean::{AgeBy, AgeCutoff, AuthorFilter, BranchDetails, Selection};
+use plan::Plan;
+use report::OutputFormat;
+use trash::{Trash, TrashEntry};
+use workspace::RepoSummary;
 
+mod auth;
+mod clean;
+mod config;
+mod error;
 mod git_utils;
 mod io_utils;
+mod picker;
+mod plan;
+mod pool;
+mod protect;
+mod report;
+mod stash;
+mod tags;
+mod time_utils;
+mod trash;
+mod workspace;
+mod worktree;
 
 #[derive(Parser)]
 #[command(name = "purgit")]
@@ -23,31 +46,222 @@ struct Cli {
     #[arg(short, long, global = true)]
     verbose: bool,
 
+    #[arg(short = 'C', value_name = "PATH", global = true)]
+    directory: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH", global = true)]
+    git_dir: Option<PathBuf>,
+
+    #[arg(long, value_name = "DURATION", value_parser = config::parse_timeout, global = true)]
+    timeout: Option<Duration>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[command(group(ArgGroup::new("repos").multiple(true)))]
     Clean {
+        #[command(flatten)]
+        scan: ScanArgs,
+
+        #[arg(short, long)]
+        yes: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, value_name = "FILE")]
+        plan_out: Option<PathBuf>,
+
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+
+        #[arg(short, long, conflicts_with = "format")]
+        interactive: bool,
+
+        #[arg(long)]
+        unpushed: bool,
+
+        #[arg(long, requires = "unpushed")]
+        force: bool,
+
+        #[arg(long, value_name = "DIR", group = "repos", conflicts_with_all = ["interactive", "plan_out", "format"])]
+        recursive: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE", group = "repos", conflicts_with_all = ["interactive", "plan_out", "format"])]
+        repos_from: Option<PathBuf>,
+
+        #[arg(long, requires = "repos")]
+        per_repo: bool,
+    },
+    Report {
+        #[command(flatten)]
+        scan: ScanArgs,
+
+        #[arg(long)]
+        by_author: bool,
+
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    Tags {
+        #[arg(long, value_name = "PATTERN")]
+        pattern: Option<String>,
+
+        #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+        stale: Option<Duration>,
+
+        #[arg(long, visible_alias = "prune", conflicts_with = "delete_remote")]
+        local_only: bool,
+
+        #[arg(long, requires = "pattern")]
+        delete_remote: bool,
+
+        #[arg(long, value_name = "NAME")]
+        remote: Option<String>,
+
+        #[arg(long, visible_alias = "offline", conflicts_with_all = ["local_only", "delete_remote"])]
+        no_fetch: bool,
+
+        #[arg(short, long)]
+        yes: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    Stash {
+        #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+        older_than: Option<Duration>,
+
+        #[arg(long)]
+        applied: bool,
+
         #[arg(short, long)]
         yes: bool,
 
-        #[arg(long, default_value_t = 30)]
-        stale: u64,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Worktrees {
+        #[arg(long)]
+        prune: bool,
+
+        #[arg(long, value_name = "BASE", num_args = 0..=1, default_missing_value = "")]
+        merged: Option<String>,
+
+        #[arg(short, long)]
+        yes: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Apply {
+        plan: PathBuf,
+
+        #[arg(short, long)]
+        yes: bool,
+    },
+    Restore {
+        #[arg(required_unless_present = "last_run")]
+        name: Option<String>,
+
+        #[arg(long, conflicts_with = "name")]
+        last_run: bool,
+    },
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    List,
+    Purge {
+        #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+        older_than: Option<Duration>,
+
+        #[arg(short, long)]
+        yes: bool,
     },
 }
 
-#[derive(Debug)]
-struct BranchDetails {
-    name: String,
-    kind: String,
-    age: u64,
+#[derive(Subcommand)]
+enum ConfigCommands {
+    Show,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Options selecting which branches are scanned.
+#[derive(Args)]
+struct ScanArgs {
+    #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+    stale: Option<Duration>,
+
+    #[arg(long, value_name = "DATE", value_parser = time_utils::parse_date, conflicts_with = "stale")]
+    before: Option<i64>,
+
+    #[arg(long, value_name = "DATE", value_parser = time_utils::parse_date, conflicts_with = "stale")]
+    after: Option<i64>,
+
+    #[arg(long, value_enum, default_value_t = AgeBy::Committer)]
+    age_by: AgeBy,
+
+    #[arg(long, value_name = "BASE", num_args = 0..=1, default_missing_value = "")]
+    merged: Option<String>,
+
+    #[arg(long)]
+    gone: bool,
+
+    #[arg(long, value_name = "PATTERN")]
+    protect: Vec<String>,
+
+    #[arg(long = "remote", value_name = "NAME", conflicts_with = "all_remotes")]
+    remotes: Vec<String>,
+
+    #[arg(long)]
+    all_remotes: bool,
+
+    #[arg(long, visible_alias = "offline", conflicts_with = "fetch_if_older_than")]
+    no_fetch: bool,
+
+    #[arg(long, value_name = "DURATION", value_parser = time_utils::parse_duration)]
+    fetch_if_older_than: Option<Duration>,
+
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    #[arg(long, conflicts_with = "author")]
+    mine: bool,
+
+    #[arg(short, long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    #[arg(long)]
+    fail_on_stale: bool,
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", format!("Error: {}", e).red());
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Error> {
     if cli.verbose {
         // Set up logging
         Builder::from_env(Env::default().default_filter_or("debug"))
@@ -67,118 +281,708 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .init();
     }
 
+    // Like git, everything else happens relative to the -C directory
+    if let Some(ref directory) = cli.directory {
+        std::env::set_current_dir(directory).map_err(|e| format!("Cannot change to {}: {}", directory.display(), e))?;
+    }
+
     match &cli.command {
-        Commands::Clean {stale, yes} => {
-            // Initialize progress bar if not quiet or verbose
-            let progress = if !(cli.quiet || cli.verbose) {
-                Some(ProgressBar::new_spinner())
-            } else { 
-                None 
+        Commands::Clean { scan, yes, dry_run, plan_out, format, interactive, unpushed, force, recursive, repos_from, per_repo } => {
+            if recursive.is_some() || repos_from.is_some() {
+                let mut paths = Vec::new();
+                if let Some(dir) = recursive {
+                    paths.extend(workspace::find_repositories(dir)?);
+                }
+                if let Some(file) = repos_from {
+                    paths.extend(workspace::read_repository_list(file)?);
+                }
+                let unpushed = unpushed.then_some(*force);
+                return clean_repositories(cli, scan, &paths, *yes, *dry_run, unpushed, *per_repo);
+            }
+
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Fetching...");
+
+            let repo = open_repository(cli)?;
+            let config = load_config(&repo, scan)?;
+            let format = match format {
+                Some(format) => *format,
+                None => OutputFormat::parse(&config.format.value)?,
             };
+            // Only the report itself is printed in machine-readable formats
+            let quiet = cli.quiet || format != OutputFormat::Text;
 
-            // Set up progress bar
-            if let Some(ref progress) = progress {
-                progress.set_message("Fetching...");
-                progress.enable_steady_tick(Duration::from_millis(100));
-                progress.set_style(ProgressStyle::default_spinner()
-                    .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
-                    .template("{spinner} {msg}")
-                    .expect("Invalid template"));
+            let (mut branches, label) = scan_repository(&repo, &config, scan, progress.as_ref(), cli.verbose, usize::from(scan.jobs))?;
+            if *unpushed {
+                clean::check_unpushed(&repo, &mut branches, *force)?;
             }
-            
-            let repo = Repository::open(".").expect("No Git repository found in current directory.");
-            git_utils::fetch_remote(&repo, "origin")?;
+            let stale_count = branches.iter().filter(|b| b.skipped.is_none()).count();
 
             if let Some(ref progress) = progress {
-                progress.set_message("Scanning branches...");
+                progress.finish_and_clear();
             }
 
-            let mut branches = Vec::new();
-            for branch_result in repo.branches(None)? {
-                let (branch, branch_type) = branch_result?;
+            if !quiet {
+                print_header(&repo, scan.age_by);
+            }
 
-                let name = branch.name()?.unwrap_or("<invalid UTF-8>");
-                let kind = match branch_type {
-                    BranchType::Local => "local",
-                    BranchType::Remote => "remote",
+            // Only the ticked branches are kept, along with skipped ones for the report
+            if *interactive && branches.iter().any(|b| b.skipped.is_none()) {
+                let Some(ticked) = picker::pick(&repo, &branches)? else {
+                    return Err(Error::Aborted);
                 };
+                let mut ticked = ticked.into_iter();
+                branches.retain(|b| ticked.next().unwrap_or(false) || b.skipped.is_some());
+            }
 
-                let commit = branch.get().target().and_then(|oid| repo.find_commit(oid).ok());
+            if !cli.quiet {
+                report::print_branches(&branches, format, label)?;
+            }
 
-                if let Some(commit) = commit {
-                    let commit_time = commit.time().seconds() as u64;
-                    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as u64;
-                    let age = Duration::from_secs(now - commit_time).as_secs() / 86400;
-                    if age > *stale {
-                        branches.push(BranchDetails { name: name.to_string(), kind: kind.to_string(), age: age });
+            // A saved plan is only written, never applied
+            if *dry_run || plan_out.is_some() {
+                if let Some(path) = plan_out {
+                    Plan::new(&repo, &branches).save(path)?;
+                    if !quiet {
+                        println!("Wrote plan to {}.", path.display());
                     }
                 }
+                if !quiet {
+                    println!("Dry run, no branches were deleted.");
+                }
+                return check_stale(scan, stale_count);
+            }
+
+            // Selected branches are confirmed once rather than one by one
+            let yes = if *interactive {
+                let count = branches.iter().filter(|b| b.skipped.is_none()).count();
+                if !io_utils::confirm(&format!("Delete {} selected branches?", count), *yes) {
+                    return Err(Error::Aborted);
+                }
+                true
+            } else {
+                *yes
+            };
 
-                debug!("Found {}:{} branch.", kind, name);
+            let summary = clean::delete_branches(&repo, &branches, yes, progress.as_ref(), cli.verbose);
+            // Declining every branch counts as cancelling, as in multi-repository mode
+            let selected = branches.iter().filter(|b| b.skipped.is_none()).count();
+            if selected > 0 && summary.deleted + summary.failed == 0 {
+                return Err(Error::Aborted);
             }
-            
+
+            if !quiet {
+                println!("Deleted {} {} branches.", summary.deleted, label);
+            }
+            Error::check_failed(summary.failed, summary.deleted + summary.failed, "delete", "branches")?;
+            check_stale(scan, stale_count)?;
+        }
+        Commands::Report { scan, by_author, format } => {
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Fetching...");
+
+            let repo = open_repository(cli)?;
+            let config = load_config(&repo, scan)?;
+            let format = match format {
+                Some(format) => *format,
+                None => OutputFormat::parse(&config.format.value)?,
+            };
+
+            let (branches, label) = scan_repository(&repo, &config, scan, progress.as_ref(), cli.verbose, usize::from(scan.jobs))?;
+
             if let Some(ref progress) = progress {
                 progress.finish_and_clear();
             }
 
-            branches.sort_by(|a, b| b.age.cmp(&a.age));
+            if !cli.quiet && format == OutputFormat::Text {
+                print_header(&repo, scan.age_by);
+            }
+
+            if *by_author {
+                report::print_authors(&branches, format, label)?;
+            } else {
+                report::print_branches(&branches, format, label)?;
+            }
+            check_stale(scan, branches.iter().filter(|b| b.skipped.is_none()).count())?;
+        }
+        Commands::Tags { pattern, stale, local_only, delete_remote, remote, no_fetch, yes, dry_run, format } => {
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Listing remote tags...");
 
-            let max_name_len = branches
-                .iter()
-                .map(|b| b.name.len())
-                .max()
-                .unwrap_or(10);
+            let repo = open_repository(cli)?;
+            let config = Config::load(Some(&repo))?;
+            let format = match format {
+                Some(format) => *format,
+                None => OutputFormat::parse(&config.format.value)?,
+            };
+            let quiet = cli.quiet || format != OutputFormat::Text;
+            let remote = remote.clone().unwrap_or(config.remote.value);
+            let pattern = match pattern {
+                Some(pattern) => Some(glob::Pattern::new(pattern).map_err(|e| format!("Invalid tag pattern '{}': {}", pattern, e))?),
+                None => None,
+            };
+
+            // Tags are listed without the remote when it cannot be reached, but never deleted
+            let remote_tags = if *no_fetch {
+                None
+            } else {
+                match git_utils::list_remote_tags(&repo, &remote, progress.as_ref()) {
+                    Ok(remote_tags) => Some(remote_tags),
+                    Err(e) if *local_only || *delete_remote => {
+                        return Err(Error::Fetch(format!("failed to list tags on {}: {}", remote, e.message())));
+                    }
+                    Err(e) => {
+                        warn!("Failed to list tags on {}: {}", remote, e);
+                        if !cli.verbose {
+                            io_utils::print_error(progress.as_ref(), &format!("Warning: failed to list tags on {}: {}", remote, e.message()).yellow());
+                        }
+                        None
+                    }
+                }
+            };
 
+            let selection = tags::TagSelection {
+                pattern,
+                stale: *stale,
+                remote: &remote,
+                remote_tags: remote_tags.as_ref(),
+                local_only: *local_only,
+                delete_remote: *delete_remote,
+            };
+            let tags = tags::scan_tags(&repo, &selection)?;
+
+            if let Some(ref progress) = progress {
+                progress.finish_and_clear();
+            }
+
+            let deleting = *local_only || *delete_remote;
             if !cli.quiet {
-                println!("Found {} stale branches.", branches.len());
-                for branch in &branches {
-                    let branch_str = format!("{:<width$}", branch.name, width = max_name_len).green();
-                    let age_str = format!("{}d", branch.age).blue();
-                    println!(
-                        "* {}    {}",
-                        branch_str,
-                        age_str,
-                    );
+                report::print_tags(&tags, format, deleting)?;
+            }
+            if !deleting {
+                return Ok(());
+            }
+            if *dry_run {
+                if !quiet {
+                    println!("Dry run, no tags were deleted.");
                 }
+                return Ok(());
             }
 
-            if *yes && let Some(ref progress) = progress {
-                progress.set_message("");
-                progress.enable_steady_tick(Duration::from_millis(100));
-                progress.reset();
+            let summary = tags::delete_tags(&repo, &tags, *yes, progress.as_ref(), cli.verbose);
+
+            if !quiet {
+                println!("Deleted {} tags.", summary.deleted);
             }
-            
-            let mut deleted_count = 0;
+            Error::check_failed(summary.failed, summary.deleted + summary.failed, "delete", "tags")?;
+        }
+        Commands::Stash { older_than, applied, yes, dry_run } => {
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Scanning stash...");
 
-            for branch in &branches {
-                let confirmed = io_utils::confirm(&format!("Delete branch {}?", branch.name), *yes);
-                
-                if confirmed {
-                    if *yes && let Some(ref progress) = progress {
-                        progress.set_message(format!("Deleting {}...", branch.name));
-                    }
+            let mut repo = open_repository(cli)?;
+            let selection = stash::StashSelection { older_than: *older_than, applied: *applied };
+            let stashes = stash::scan_stashes(&mut repo, &selection)?;
 
-                    // Delete branch here
-                    thread::sleep(Duration::from_millis(250)); // Simulating work
+            if let Some(ref progress) = progress {
+                progress.finish_and_clear();
+            }
 
-                    deleted_count += 1;
-                    debug!("Deleted branch {}", branch.name);
+            let dropping = older_than.is_some() || *applied;
+            if !cli.quiet {
+                report::print_stashes(&stashes, dropping);
+            }
+            if !dropping {
+                return Ok(());
+            }
+            if *dry_run {
+                if !cli.quiet {
+                    println!("Dry run, no stash entries were dropped.");
                 }
-                
-                if let Some(ref progress) = progress {
-                    progress.inc(1);
+                return Ok(());
+            }
+
+            let summary = stash::drop_stashes(&mut repo, &stashes, *yes, progress.as_ref(), cli.verbose);
+
+            if !cli.quiet {
+                println!("Dropped {} stash entries.", summary.deleted);
+            }
+            Error::check_failed(summary.failed, summary.deleted + summary.failed, "drop", "stash entries")?;
+        }
+        Commands::Worktrees { prune, merged, yes, dry_run } => {
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Scanning worktrees...");
+
+            let repo = open_repository(cli)?;
+            let config = Config::load(Some(&repo))?;
+            let base = match merged {
+                Some(base) => Some(git_utils::resolve_base(&repo, Some(base.as_str()).filter(|b| !b.is_empty()), &config.remote.value)?),
+                None => None,
+            };
+            let worktrees = worktree::scan_worktrees(&repo, *prune, base.as_ref())?;
+
+            if let Some(ref progress) = progress {
+                progress.finish_and_clear();
+            }
+
+            let pruning = *prune || merged.is_some();
+            if !cli.quiet {
+                report::print_worktrees(&worktrees, pruning);
+            }
+            if !pruning {
+                return Ok(());
+            }
+            if *dry_run {
+                if !cli.quiet {
+                    println!("Dry run, no worktrees were pruned.");
                 }
+                return Ok(());
+            }
+
+            let summary = worktree::prune_worktrees(&repo, &worktrees, *yes, progress.as_ref(), cli.verbose);
+
+            if !cli.quiet {
+                println!("Pruned {} worktrees.", summary.deleted);
+            }
+            Error::check_failed(summary.failed, summary.deleted + summary.failed, "prune", "worktrees")?;
+        }
+        Commands::Apply { plan, yes } => {
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Verifying plan...");
+
+            let repo = open_repository(cli)?;
+            let plan = Plan::load(plan)?;
+            if !plan.matches_repository(&repo) {
+                return Err(format!("Plan was created for a different repository ({})", plan.repository).into());
             }
 
+            let config = Config::load(Some(&repo))?;
+            let protection = Protection::new(&repo, &config.protect_patterns())?;
+
+            // Skip branches that changed since the plan was made
+            let mut protected = 0;
+            let branches: Vec<BranchDetails> = plan.branches
+                .into_iter()
+                .map(|entry| {
+                    let skipped = entry.verify(&repo).err().or_else(|| {
+                        let reason = protection.check(&entry.name, entry.remote.as_deref());
+                        protected += usize::from(reason.is_some());
+                        reason
+                    });
+                    BranchDetails {
+                        name: entry.name,
+                        kind: entry.kind,
+                        remote: entry.remote,
+                        age: entry.age,
+                        tip: entry.tip,
+                        reason: entry.reason,
+                        skipped,
+                        ..Default::default()
+                    }
+                })
+                .collect();
+
             if let Some(ref progress) = progress {
                 progress.finish_and_clear();
             }
 
             if !cli.quiet {
-                println!("Deleted {} stale branches.", deleted_count);
+                report::print_branches(&branches, OutputFormat::Text, "planned")?;
+            }
+
+            let summary = clean::delete_branches(&repo, &branches, *yes, progress.as_ref(), cli.verbose);
+
+            if !cli.quiet {
+                println!("Deleted {} planned branches.", summary.deleted);
+            }
+            Error::check_failed(summary.failed, summary.deleted + summary.failed, "delete", "branches")?;
+            if protected > 0 {
+                return Err(Error::Protected(protected));
+            }
+        }
+        Commands::Restore { name, last_run } => {
+            let repo = open_repository(cli)?;
+            let trash = Trash::open(&repo);
+            let entries = trash.entries()?;
+
+            // Restore the most recent run, or the most recent run that deleted the named branch
+            let run = entries
+                .iter()
+                .filter(|e| *last_run || name.as_ref() == Some(&e.name))
+                .map(|e| e.run)
+                .max();
+            let Some(run) = run else {
+                return Err(match name {
+                    Some(name) => format!("No deleted branch or tag named {} found in the trash", name).into(),
+                    None => "The trash is empty".into(),
+                });
+            };
+            let selected: Vec<TrashEntry> = entries
+                .into_iter()
+                .filter(|e| e.run == run && (*last_run || name.as_ref() == Some(&e.name)))
+                .collect();
+
+            let total = selected.len();
+            let mut restored = Vec::new();
+            for entry in selected {
+                match trash.restore(&repo, &entry) {
+                    Ok(()) => {
+                        if !cli.quiet {
+                            println!("Restored {} {} at {}.", entry.describe(), entry.name.green(), entry.tip.get(..7).unwrap_or(&entry.tip));
+                        }
+                        restored.push(entry);
+                    }
+                    Err(e) => eprintln!("{}", format!("Failed to restore {} {}: {}", entry.describe(), entry.name, e).red()),
+                }
+            }
+            trash.remove(&repo, &restored)?;
+
+            if !cli.quiet {
+                println!("Restored {} branches and tags.", restored.len());
+            }
+            Error::check_failed(total - restored.len(), total, "restore", "branches and tags")?;
+        }
+        Commands::Trash { command: TrashCommands::List } => {
+            let repo = open_repository(cli)?;
+            let entries = Trash::open(&repo).entries()?;
+
+            let max_name_len = entries.iter().map(|e| e.name.len()).max().unwrap_or(10);
+
+            println!("Found {} deleted branches and tags in the trash.", entries.len());
+            for (i, entry) in entries.iter().enumerate() {
+                if i == 0 || entries[i - 1].run != entry.run {
+                    println!("{}", format!("Run at {} ({} ago):", time_utils::format_timestamp(entry.run), time_utils::format_duration(time_utils::since(entry.run))).bold());
+                }
+                println!(
+                    "* {}    {}    {}",
+                    format!("{:<width$}", entry.name, width = max_name_len).green(),
+                    format!("{:<10}", entry.kind).blue(),
+                    entry.tip.get(..7).unwrap_or(&entry.tip).dimmed(),
+                );
+            }
+        }
+        Commands::Trash { command: TrashCommands::Purge { older_than, yes } } => {
+            let repo = open_repository(cli)?;
+            let trash = Trash::open(&repo);
+            let purged: Vec<TrashEntry> = trash.entries()?
+                .into_iter()
+                .filter(|e| older_than.is_none_or(|max_age| time_utils::since(e.run) > max_age))
+                .collect();
+
+            if purged.is_empty() {
+                if !cli.quiet {
+                    println!("Nothing to purge.");
+                }
+                return Ok(());
+            }
+
+            if io_utils::confirm(&format!("Permanently remove {} deleted branches from the trash?", purged.len()), *yes) {
+                trash.remove(&repo, &purged)?;
+                if !cli.quiet {
+                    println!("Purged {} deleted branches.", purged.len());
+                }
+            } else {
+                return Err(Error::Aborted);
+            }
+        }
+        Commands::Config { command: ConfigCommands::Show } => {
+            // Only the repository is looked up here, so that errors in its config are shown
+            let repo = discover_repository(cli).ok();
+            let config = Config::load(repo.as_ref())?;
+
+            println!("{} = {}    {}", format!("{:<7}", "stale").green(), time_utils::format_duration(config.stale.value), format!("({})", config.stale.source).dimmed());
+            println!("{} = {}    {}", format!("{:<7}", "remote").green(), config.remote.value, format!("({})", config.remote.source).dimmed());
+            println!("{} = {}    {}", format!("{:<7}", "format").green(), config.format.value, format!("({})", config.format.source).dimmed());
+            println!("{} = {}    {}", format!("{:<7}", "timeout").green(), time_utils::format_duration(config.timeout.value), format!("({})", config.timeout.source).dimmed());
+            if config.protect.is_empty() {
+                println!("{} = []", format!("{:<7}", "protect").green());
+            }
+            for pattern in &config.protect {
+                println!("{} = {}    {}", format!("{:<7}", "protect").green(), pattern.value, format!("({})", pattern.source).dimmed());
             }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// A scanned repository with its branches and the label describing how they were selected.
+type ScannedRepo = (Repository, Vec<BranchDetails>, &'static str);
+
+/// Runs `clean` in every given repository.
+///
+/// Each repository is scanned and reported in its own section before anything is
+/// deleted, then deletion is confirmed once for all repositories, or once per
+/// repository with `per_repo`. A repository that cannot be opened or scanned is
+/// reported in the summary and does not stop the others.
+fn clean_repositories(cli: &Cli, scan: &ScanArgs, paths: &[PathBuf], yes: bool, dry_run: bool, unpushed: Option<bool>, per_repo: bool) -> Result<(), Error> {
+    // Repositories are fetched concurrently, so only the global and command line timeouts apply
+    set_network_timeout(cli, None)?;
+
+    // Each repository gets its own progress line while the pool works through them
+    let multi = (!cli.quiet && !cli.verbose).then(MultiProgress::new);
+
+    let scan_one = |path: &PathBuf| {
+        let mut summary = RepoSummary { name: path.display().to_string(), path: path.clone(), ..Default::default() };
+        let progress = multi.as_ref().map(|multi| io_utils::add_spinner(multi, &format!("{}: Scanning...", summary.name)));
+        let result = Repository::open(path).map_err(|e| e.into()).and_then(|repo| {
+            summary.name = git_utils::resolve_name(&repo).unwrap_or(summary.name.clone());
+            if let Some(ref progress) = progress {
+                progress.set_prefix(format!("{}: ", summary.name));
+                progress.set_message("Scanning...");
+            }
+
+            // Repositories are already scanned in parallel, so their remotes are fetched one at a time
+            let config = load_config(&repo, scan)?;
+            let (mut branches, label) = scan_repository(&repo, &config, scan, progress.as_ref(), cli.verbose, 1)?;
+            if let Some(force) = unpushed {
+                clean::check_unpushed(&repo, &mut branches, force)?;
+            }
+            Ok::<_, Box<dyn std::error::Error>>((repo, branches, label))
+        });
+
+        if let Some(progress) = progress {
+            progress.finish_and_clear();
+        }
+        (summary, result.map_err(|e| e.to_string()))
+    };
+
+    // Sections are printed as soon as each repository is scanned, and kept in path order for the summary
+    let mut repos: Vec<Option<(RepoSummary, Option<ScannedRepo>)>> = paths.iter().map(|_| None).collect();
+    pool::run(paths, usize::from(scan.jobs), scan_one, |index, (mut summary, result)| {
+        let scanned = match result {
+            Ok((repo, branches, label)) => {
+                summary.skipped = branches.iter().filter(|b| b.skipped.is_some()).count();
+                summary.selected = branches.len() - summary.skipped;
+                Some((repo, branches, label))
+            }
+            Err(e) => {
+                warn!("Failed to scan {}: {}", summary.path.display(), e);
+                summary.error = Some(e);
+                None
+            }
+        };
+
+        if !cli.quiet {
+            let print_section = || {
+                println!("{}", format!("== {} ({}) ==", summary.name, summary.path.display()).bold());
+                match scanned {
+                    Some((_, ref branches, label)) => report::print_branches(branches, OutputFormat::Text, label).map_err(|e| e.to_string()),
+                    None => {
+                        println!("{}", format!("Failed to scan: {}", summary.error.as_deref().unwrap_or_default()).red());
+                        Ok(())
+                    }
+                }?;
+                println!();
+                Ok::<_, String>(())
+            };
+            let printed = match multi {
+                Some(ref multi) => multi.suspend(print_section),
+                None => print_section(),
+            };
+            if let Err(e) = printed {
+                warn!("Failed to print the report for {}: {}", summary.name, e);
+            }
+        }
+
+        repos[index] = Some((summary, scanned));
+    });
+    let mut repos: Vec<_> = repos.into_iter().flatten().collect();
+
+    let selected: usize = repos.iter().map(|(s, _)| s.selected).sum();
+    let repo_count = repos.iter().filter(|(s, _)| s.selected > 0).count();
+    let confirmed = if dry_run || selected == 0 {
+        false
+    } else if per_repo {
+        true
+    } else {
+        io_utils::confirm(&format!("Delete {} branches in {} repositories?", selected, repo_count), yes)
+    };
+
+    if confirmed {
+        for (summary, scanned) in repos.iter_mut() {
+            let Some((repo, branches, _)) = scanned else {
+                continue;
+            };
+            if summary.selected == 0
+                || (per_repo && !io_utils::confirm(&format!("Delete {} branches in {}?", summary.selected, summary.name), yes))
+            {
+                continue;
+            }
+
+            let progress = io_utils::spinner(cli.quiet || cli.verbose, "Deleting...");
+            let deleted = clean::delete_branches(repo, branches, true, progress.as_ref(), cli.verbose);
+            summary.deleted = deleted.deleted;
+            summary.failed = deleted.failed;
+        }
+    }
+
+    let summaries: Vec<RepoSummary> = repos.into_iter().map(|(summary, _)| summary).collect();
+    if !cli.quiet {
+        report::print_repo_summary(&summaries);
+        if dry_run {
+            println!("Dry run, no branches were deleted.");
+        }
+    }
+
+    // Declining every prompt, including each one of `--per-repo`, counts as cancelling
+    let deleted: usize = summaries.iter().map(|s| s.deleted).sum();
+    let failed: usize = summaries.iter().map(|s| s.failed).sum();
+    if !dry_run && selected > 0 && deleted + failed == 0 {
+        return Err(Error::Aborted);
+    }
+    Error::check_failed(failed, deleted + failed, "delete", "branches")?;
+    let unscanned = summaries.iter().filter(|s| s.error.is_some()).count();
+    Error::check_failed(unscanned, summaries.len(), "scan", "repositories")?;
+    check_stale(scan, selected)
+}
+
+/// Fails with `--fail-on-stale` when any stale branches were selected.
+fn check_stale(args: &ScanArgs, selected: usize) -> Result<(), Error> {
+    if args.fail_on_stale && selected > 0 {
+        return Err(Error::StaleBranches(selected));
+    }
+    Ok(())
+}
+
+/// Opens the repository to work on and applies its network timeout.
+fn open_repository(cli: &Cli) -> Result<Repository, Error> {
+    let repo = discover_repository(cli)?;
+    set_network_timeout(cli, Some(&repo))?;
+    Ok(repo)
+}
+
+/// Finds the repository to work on, the way git finds it.
+///
+/// An explicit `--git-dir` is opened as is. Otherwise `GIT_DIR` is honoured when
+/// set, and the repository is discovered from the current directory upwards,
+/// stopping at `GIT_CEILING_DIRECTORIES`. Bare repositories are supported.
+fn discover_repository(cli: &Cli) -> Result<Repository, Error> {
+    let repo = match cli.git_dir {
+        Some(ref git_dir) => Repository::open(git_dir).map_err(|e| {
+            Error::NoRepository(format!("Not a git repository: {} ({})", git_dir.display(), e.message()))
+        })?,
+        None => Repository::open_from_env().map_err(|_| {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            Error::NoRepository(format!("No git repository found in {} or any parent directory", cwd.display()))
+        })?,
+    };
+
+    debug!("Opened repository at {}.", repo.path().display());
+    Ok(repo)
+}
+
+/// Applies the network timeout from the config, or from the command line.
+fn set_network_timeout(cli: &Cli, repo: Option<&Repository>) -> Result<(), Error> {
+    let mut timeout = Config::load(repo)?.timeout;
+    timeout.override_with(cli.timeout);
+    git_utils::set_network_timeout(timeout.value)?;
+    Ok(())
+}
+
+/// Loads the layered config, overridden by the scan options given on the command line.
+fn load_config(repo: &Repository, args: &ScanArgs) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config = Config::load(Some(repo))?;
+    config.stale.override_with(args.stale);
+    config.protect.extend(args.protect.iter().map(|p| Setting { value: p.clone(), source: Source::Cli }));
+    Ok(config)
+}
+
+/// Fetches the selected remotes and scans the repository for matching branches.
+///
+/// Returns the branches along with a label describing how they were selected.
+fn scan_repository(repo: &Repository, config: &Config, args: &ScanArgs, progress: Option<&ProgressBar>, verbose: bool, jobs: usize) -> Result<(Vec<BranchDetails>, &'static str), Box<dyn std::error::Error>> {
+    let remotes = if args.all_remotes {
+        repo.remotes()?.iter().flatten().map(str::to_string).collect()
+    } else if !args.remotes.is_empty() {
+        args.remotes.clone()
+    } else {
+        vec![config.remote.value.clone()]
+    };
+
+    let fetch = match args.fetch_if_older_than {
+        _ if args.no_fetch => false,
+        Some(max_age) => git_utils::last_fetch_age(repo).is_none_or(|age| age > max_age),
+        None => true,
+    };
+    if !fetch {
+        debug!("Skipping fetch, using existing remote-tracking branches.");
+    }
+
+    // Remotes are fetched concurrently, each on its own line below the main spinner.
+    // A remote that fails to fetch is still scanned using its existing remote-tracking branches
+    let fetched: Vec<&String> = remotes.iter().filter(|_| fetch).collect();
+    let multi = progress.filter(|_| jobs > 1 && fetched.len() > 1).map(|progress| {
+        let multi = MultiProgress::new();
+        multi.add(progress.clone());
+        multi
+    });
+    let repo_path = repo.path().to_path_buf();
+
+    let fetch_one = |remote: &&String| {
+        let line = match multi {
+            Some(ref multi) => Some(io_utils::add_spinner(multi, &format!("Fetching {}...", remote))),
+            None => {
+                if let Some(progress) = progress {
+                    progress.set_message(format!("Fetching {}...", remote));
+                }
+                None
+            }
+        };
+        let result = Repository::open(&repo_path).and_then(|repo| git_utils::fetch_remote(&repo, remote, line.as_ref().or(progress)));
+        if let Some(line) = line {
+            line.finish_and_clear();
+        }
+        result
+    };
+    pool::run(&fetched, jobs, fetch_one, |index, result| {
+        if let Err(e) = result {
+            warn!("Failed to fetch {}: {}", fetched[index], e);
+            if !verbose {
+                io_utils::print_error(progress, &format!("Warning: failed to fetch {}: {}", fetched[index], e.message()).yellow());
+            }
+        }
+    });
+
+    if let Some(progress) = progress {
+        progress.set_message("Scanning branches...");
+    }
+
+    // Resolve the base branch when looking for merged branches
+    let base = match args.merged {
+        Some(ref base) => Some(git_utils::resolve_base(repo, Some(base.as_str()).filter(|b| !b.is_empty()), remotes.first().unwrap_or(&config.remote.value))?),
+        None => None,
+    };
+    // Branches are compared against the main branch even when not looking for merged branches
+    let mainline = match base {
+        Some(ref base) => Some(base.clone()),
+        None => git_utils::resolve_base(repo, None, remotes.first().unwrap_or(&config.remote.value)).ok(),
+    };
+    let label = match (base.is_some(), args.gone) {
+        (true, true) => "merged or gone",
+        (true, false) => "merged",
+        (false, true) => "gone",
+        (false, false) => "stale",
+    };
+
+    let author = if args.mine {
+        let email = repo.config()?.get_string("user.email")
+            .map_err(|_| "--mine requires user.email to be set in git config")?;
+        Some(AuthorFilter::Email(email))
+    } else {
+        args.author.clone().map(AuthorFilter::Pattern)
+    };
+
+    let protection = Protection::new(repo, &config.protect_patterns())?;
+    let age_cutoff = match (args.before, args.after) {
+        (None, None) => AgeCutoff::Stale(config.stale.value),
+        (before, after) => AgeCutoff::Between { before, after },
+    };
+    let selection = Selection { age: age_cutoff, age_by: args.age_by, base, gone: args.gone, mainline, remotes: &remotes, author, protection: &protection };
+
+    Ok((clean::scan_branches(repo, &selection)?, label))
+}
+
+/// Prints how long ago the remote-tracking branches were last updated, and
+/// which timestamp branch ages are based on.
+fn print_header(repo: &Repository, age_by: AgeBy) {
+    match git_utils::last_fetch_age(repo) {
+        Some(age) => println!("Remote-tracking data was fetched {} ago.", time_utils::format_duration(age)),
+        None => println!("Remote-tracking data has never been fetched."),
+    }
+    println!("Branch age is based on the {}.", age_by.description());
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::time::SystemTime;
+
+use git2::{Oid, Repository};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use crate::clean::BranchDetails;
+use crate::time_utils;
+
+/// Number of commits shown in the preview pane.
+const PREVIEW_COMMITS: usize = 20;
+
+/// Order in which candidate branches are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Age,
+    Name,
+    Author,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Age => SortKey::Name,
+            SortKey::Name => SortKey::Author,
+            SortKey::Author => SortKey::Age,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Age => "age",
+            SortKey::Name => "name",
+            SortKey::Author => "author",
+        }
+    }
+}
+
+/// State of the branch picker.
+struct Picker<'a> {
+    repo: &'a Repository,
+    branches: &'a [BranchDetails],
+    ticked: Vec<bool>,
+    visible: Vec<usize>,
+    list_state: ListState,
+    sort: SortKey,
+    filter: String,
+    editing_filter: bool,
+    previews: HashMap<String, Vec<String>>,
+}
+
+/// Lets the user tick the branches to delete in a full-screen picker.
+///
+/// Skipped branches are not offered. Returns which of `branches` were ticked,
+/// or `None` if the picker was cancelled. Fails when not run in a terminal.
+pub fn pick(repo: &Repository, branches: &[BranchDetails]) -> io::Result<Option<Vec<bool>>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("Interactive mode needs a terminal"));
+    }
+
+    let mut picker = Picker {
+        repo,
+        branches,
+        ticked: vec![false; branches.len()],
+        visible: Vec::new(),
+        list_state: ListState::default(),
+        sort: SortKey::Age,
+        filter: String::new(),
+        editing_filter: false,
+        previews: HashMap::new(),
+    };
+    picker.refresh();
+
+    let mut terminal = ratatui::try_init()?;
+    let result = picker.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Picker<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<Option<Vec<bool>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.editing_filter {
+                match key.code {
+                    KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                        self.refresh();
+                    }
+                    KeyCode::Char(c) => {
+                        self.filter.push(c);
+                        self.refresh();
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => return Ok(Some(self.ticked.clone())),
+                KeyCode::Down | KeyCode::Char('j') => self.list_state.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.list_state.select_previous(),
+                KeyCode::Char(' ') => {
+                    if let Some(index) = self.current() {
+                        self.ticked[index] = !self.ticked[index];
+                    }
+                }
+                KeyCode::Char('a') => self.tick_visible(true),
+                KeyCode::Char('n') => self.tick_visible(false),
+                KeyCode::Char('s') => {
+                    self.sort = self.sort.next();
+                    self.refresh();
+                }
+                KeyCode::Char('/') => self.editing_filter = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// Recomputes the visible branches after the filter or sort order changed.
+    fn refresh(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = (0..self.branches.len())
+            .filter(|&i| self.branches[i].skipped.is_none())
+            .filter(|&i| {
+                let branch = &self.branches[i];
+                filter.is_empty()
+                    || branch.name.to_lowercase().contains(&filter)
+                    || branch.author.to_lowercase().contains(&filter)
+            })
+            .collect();
+
+        let branches = self.branches;
+        match self.sort {
+            SortKey::Age => self.visible.sort_by_key(|&i| std::cmp::Reverse(branches[i].age)),
+            SortKey::Name => self.visible.sort_by(|&a, &b| branches[a].name.cmp(&branches[b].name)),
+            SortKey::Author => self.visible.sort_by(|&a, &b| branches[a].author.cmp(&branches[b].author)),
+        }
+
+        let selected = if self.visible.is_empty() { None } else { Some(0) };
+        self.list_state.select(selected);
+    }
+
+    /// Returns the index of the highlighted branch.
+    fn current(&self) -> Option<usize> {
+        self.list_state.selected().and_then(|i| self.visible.get(i).copied())
+    }
+
+    fn tick_visible(&mut self, ticked: bool) {
+        for &i in &self.visible {
+            self.ticked[i] = ticked;
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .split(frame.area());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+            .split(rows[0]);
+
+        let ticked_count = self.ticked.iter().filter(|t| **t).count();
+        let offered_count = self.branches.iter().filter(|b| b.skipped.is_none()).count();
+        let items: Vec<ListItem> = self.visible
+            .iter()
+            .map(|&i| {
+                let branch = &self.branches[i];
+                let checkbox = if self.ticked[i] { "[x]" } else { "[ ]" };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{} ", checkbox)),
+                    Span::styled(branch.name.clone(), Style::default().fg(Color::Green)),
+                    Span::styled(format!("  {}", time_utils::format_age(branch.age)), Style::default().fg(Color::Blue)),
+                    Span::styled(format!("  {}", branch.author), Style::default().add_modifier(Modifier::DIM)),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                " Branches ({} of {} selected, sorted by {}) ",
+                ticked_count,
+                offered_count,
+                self.sort.label(),
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, columns[0], &mut self.list_state);
+
+        let preview: Vec<Line> = match self.current() {
+            Some(index) => self.preview(index).iter().map(|l| Line::raw(l.clone())).collect(),
+            None => Vec::new(),
+        };
+        let preview = Paragraph::new(preview)
+            .block(Block::default().borders(Borders::ALL).title(" Recent commits "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(preview, columns[1]);
+
+        let footer = if self.editing_filter || !self.filter.is_empty() {
+            format!("Filter: {}{}", self.filter, if self.editing_filter { "_" } else { "" })
+        } else {
+            "space: toggle  a/n: select all/none  s: sort  /: filter  enter: delete selected  q: cancel".to_string()
+        };
+        frame.render_widget(Paragraph::new(footer), rows[1]);
+    }
+
+    /// Returns the recent commits of the given branch, reading them on first use.
+    fn preview(&mut self, index: usize) -> &[String] {
+        let tip = &self.branches[index].tip;
+        let repo = self.repo;
+        self.previews
+            .entry(tip.clone())
+            .or_insert_with(|| recent_commits(repo, tip).unwrap_or_else(|e| vec![format!("Could not read commits: {}", e.message())]))
+    }
+}
+
+/// Lists the most recent commits reachable from `tip`, one line each.
+fn recent_commits(repo: &Repository, tip: &str) -> Result<Vec<String>, git2::Error> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(Oid::from_str(tip)?)?;
+
+    let mut lines = Vec::new();
+    for oid in revwalk.take(PREVIEW_COMMITS) {
+        let commit = repo.find_commit(oid?)?;
+        let age = std::time::Duration::from_secs(now.saturating_sub(commit.time().seconds() as u64));
+        lines.push(format!(
+            "{} {} ({} ago, {})",
+            &commit.id().to_string()[..7],
+            commit.summary().unwrap_or(""),
+            time_utils::format_duration(age),
+            commit.author().name().unwrap_or(""),
+        ));
+    }
+    Ok(lines)
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use git2::{Oid, Repository};
+use crate::clean::BranchDetails;
+
+/// Version of the plan file format.
+const PLAN_VERSION: u32 = 1;
+
+/// A single branch deletion recorded in a plan.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub name: String,
+    pub kind: String,
+    pub remote: Option<String>,
+    pub age: u64,
+    pub reason: String,
+    pub tip: String,
+}
+
+/// A saved list of branch deletions that can be reviewed and applied later.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub repository: String,
+    pub created: u64,
+    pub branches: Vec<PlanEntry>,
+}
+
+impl Plan {
+    /// Builds a plan deleting every branch in `branches` that is not skipped.
+    pub fn new(repo: &Repository, branches: &[BranchDetails]) -> Self {
+        let entries = branches
+            .iter()
+            .filter(|b| b.skipped.is_none())
+            .map(|b| PlanEntry {
+                name: b.name.clone(),
+                kind: b.kind.clone(),
+                remote: b.remote.clone(),
+                age: b.age,
+                reason: b.reason.clone(),
+                tip: b.tip.clone(),
+            })
+            .collect();
+
+        Plan {
+            version: PLAN_VERSION,
+            repository: repository_path(repo),
+            created: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
+            branches: entries,
+        }
+    }
+
+    /// Reads a plan from the given JSON file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let plan: Plan = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid plan file {}: {}", path.display(), e))?;
+        if plan.version != PLAN_VERSION {
+            return Err(format!("Unsupported plan version {}", plan.version).into());
+        }
+        debug!("Loaded plan with {} branches.", plan.branches.len());
+        Ok(plan)
+    }
+
+    /// Writes the plan to the given file as JSON.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        debug!("Wrote plan to {}.", path.display());
+        Ok(())
+    }
+
+    /// Checks that the plan was made for the given repository.
+    pub fn matches_repository(&self, repo: &Repository) -> bool {
+        self.repository == repository_path(repo)
+    }
+}
+
+impl PlanEntry {
+    /// Checks that the branch still exists and points at the recorded tip.
+    ///
+    /// Returns a description of the mismatch if it does not.
+    pub fn verify(&self, repo: &Repository) -> Result<(), String> {
+        let refname = match self.kind.as_str() {
+            "local" => format!("refs/heads/{}", self.name),
+            _ => format!("refs/remotes/{}", self.name),
+        };
+        let expected = Oid::from_str(&self.tip).map_err(|_| format!("invalid tip {}", self.tip))?;
+
+        match repo.refname_to_id(&refname) {
+            Ok(oid) if oid == expected => Ok(()),
+            Ok(oid) => Err(format!("tip moved from {} to {}", short(&self.tip), short(&oid.to_string()))),
+            Err(_) => Err("branch no longer exists".to_string()),
+        }
+    }
+}
+
+/// Returns the canonical path of the repository's git directory.
+///
+/// Linked worktrees share the git directory of their main worktree, so a plan
+/// made in one worktree can be applied from another.
+fn repository_path(repo: &Repository) -> String {
+    repo.commondir()
+        .canonicalize()
+        .unwrap_or_else(|_| repo.commondir().to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn short(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
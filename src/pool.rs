@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `task` on every item on at most `jobs` worker threads.
+///
+/// `done` is called on the calling thread with the index of each item and its
+/// result as soon as that item finishes, so a slow item never holds back the
+/// results of the others.
+pub fn run<T, R>(items: &[T], jobs: usize, task: impl Fn(&T) -> R + Sync, mut done: impl FnMut(usize, R))
+where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            let sender = sender.clone();
+            let (next, task) = (&next, &task);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, task(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Only the workers hold senders now, so the loop ends once they all finish
+        drop(sender);
+
+        for (index, result) in receiver {
+            done(index, result);
+        }
+    });
+}
@@ -0,0 +1,86 @@
+use glob::Pattern;
+use log::debug;
+use git2::Repository;
+use crate::git_utils;
+
+/// Branch names that are always protected.
+const DEFAULT_PROTECTED: [&str; 3] = ["main", "master", "develop"];
+
+/// A set of rules deciding which branches must never be deleted.
+pub struct Protection {
+    head: Option<String>,
+    worktrees: Vec<(String, String)>,
+    remote_defaults: Vec<(String, String)>,
+    patterns: Vec<Pattern>,
+}
+
+impl Protection {
+    /// Builds the protection rules for the given repository.
+    ///
+    /// Protected branches are the checked out branch, branches checked out in other
+    /// worktrees, the default branch of each remote, the built-in
+    /// `main`/`master`/`develop` names, and the given glob patterns.
+    pub fn new(repo: &Repository, patterns: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let head = repo
+            .head()
+            .ok()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand().map(str::to_string));
+
+        let worktrees = git_utils::checked_out_branches(repo)?;
+
+        let mut remote_defaults = Vec::new();
+        for remote in repo.remotes()?.iter().flatten() {
+            let default = repo
+                .find_reference(&format!("refs/remotes/{}/HEAD", remote))
+                .ok()
+                .and_then(|r| r.symbolic_target().map(str::to_string))
+                .and_then(|target| target.strip_prefix(&format!("refs/remotes/{}/", remote)).map(str::to_string));
+            if let Some(default) = default {
+                debug!("Default branch of {} is {}.", remote, default);
+                remote_defaults.push((remote.to_string(), default));
+            }
+        }
+
+        let patterns = patterns
+            .iter()
+            .map(|p| Pattern::new(p).map_err(|e| format!("Invalid protect pattern '{}': {}", p, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Protection { head, worktrees, remote_defaults, patterns })
+    }
+
+    /// Returns the reason the given branch is protected, if it is.
+    ///
+    /// Remote branches are given as `<remote>/<name>`, and name-based rules are
+    /// matched against the name without the remote prefix.
+    pub fn check(&self, name: &str, remote: Option<&str>) -> Option<String> {
+        let short_name = match remote {
+            Some(remote) => name.strip_prefix(remote).and_then(|n| n.strip_prefix('/')).unwrap_or(name),
+            None => {
+                if self.head.as_deref() == Some(name) {
+                    return Some("current HEAD".to_string());
+                }
+                if let Some((_, worktree)) = self.worktrees.iter().find(|(branch, _)| branch == name) {
+                    return Some(format!("checked out in {}", worktree));
+                }
+                name
+            }
+        };
+
+        for (remote_name, default) in &self.remote_defaults {
+            if default == short_name && remote.is_none_or(|r| r == remote_name) {
+                return Some(format!("default branch of {}", remote_name));
+            }
+        }
+
+        if DEFAULT_PROTECTED.contains(&short_name) {
+            return Some("protected by default".to_string());
+        }
+
+        self.patterns
+            .iter()
+            .find(|p| p.matches(short_name))
+            .map(|p| format!("matches '{}'", p.as_str()))
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::io;
+use colored::*;
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::clean::BranchDetails;
+use crate::stash::StashDetails;
+use crate::tags::TagDetails;
+use crate::worktree::WorktreeDetails;
+use crate::workspace::RepoSummary;
+use crate::time_utils;
+
+/// Output format of the branch report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Parses a format name as used in config files.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        OutputFormat::from_str(name, true).map_err(|_| format!("Unknown output format '{}'", name))
+    }
+}
+
+/// Prints the branch report in the given format.
+///
+/// Machine-readable formats include skipped branches along with every recorded field.
+pub fn print_branches(branches: &[BranchDetails], format: OutputFormat, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Text => print_text(branches, label),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(io::stdout().lock(), branches)?;
+            println!();
+        }
+        OutputFormat::Csv => write_delimited(branches, b',')?,
+        OutputFormat::Tsv => write_delimited(branches, b'\t')?,
+    }
+    Ok(())
+}
+
+/// Branch counts for a single author.
+#[derive(Debug, Serialize)]
+struct AuthorSummary {
+    author: String,
+    branches: usize,
+    oldest: u64,
+}
+
+/// Prints the selected branches grouped per author, with counts and the oldest age.
+///
+/// Skipped branches are not counted.
+pub fn print_authors(branches: &[BranchDetails], format: OutputFormat, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut by_author: BTreeMap<&str, AuthorSummary> = BTreeMap::new();
+    for branch in branches.iter().filter(|b| b.skipped.is_none()) {
+        let summary = by_author.entry(&branch.author).or_insert_with(|| AuthorSummary {
+            author: branch.author.clone(),
+            branches: 0,
+            oldest: 0,
+        });
+        summary.branches += 1;
+        summary.oldest = summary.oldest.max(branch.age);
+    }
+
+    let mut authors: Vec<AuthorSummary> = by_author.into_values().collect();
+    authors.sort_by(|a, b| b.branches.cmp(&a.branches).then(b.oldest.cmp(&a.oldest)));
+
+    match format {
+        OutputFormat::Text => {
+            let max_author_len = authors.iter().map(|a| a.author.len()).max().unwrap_or(10);
+            let total: usize = authors.iter().map(|a| a.branches).sum();
+            println!("Found {} {} branches by {} authors.", total, label, authors.len());
+            for author in &authors {
+                println!(
+                    "* {}    {:>4} branches    {}",
+                    format!("{:<width$}", author.author, width = max_author_len).green(),
+                    author.branches,
+                    format!("oldest {}", time_utils::format_age(author.oldest)).blue(),
+                );
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(io::stdout().lock(), &authors)?;
+            println!();
+        }
+        OutputFormat::Csv => write_delimited(&authors, b',')?,
+        OutputFormat::Tsv => write_delimited(&authors, b'\t')?,
+    }
+    Ok(())
+}
+
+/// Prints a table of how many branches were selected and deleted in each repository,
+/// followed by the totals.
+pub fn print_repo_summary(summaries: &[RepoSummary]) {
+    let max_name_len = summaries.iter().map(|s| s.name.len()).max().unwrap_or(10).max("total".len());
+
+    println!("{}", format!("{:<width$}    {:>8}    {:>7}    {:>7}    {:>6}", "repository", "selected", "skipped", "deleted", "failed", width = max_name_len).bold());
+    for summary in summaries {
+        let name_str = format!("{:<width$}", summary.name, width = max_name_len);
+        match summary.error {
+            Some(ref error) => println!("{}    {}", name_str.red(), format!("error: {}", error).red()),
+            None => println!(
+                "{}    {:>8}    {:>7}    {:>7}    {}",
+                name_str.green(),
+                summary.selected,
+                summary.skipped,
+                summary.deleted,
+                color_failed(summary.failed),
+            ),
+        }
+    }
+
+    let total = |count: fn(&RepoSummary) -> usize| summaries.iter().map(count).sum::<usize>();
+    println!(
+        "{}    {:>8}    {:>7}    {:>7}    {}",
+        format!("{:<width$}", "total", width = max_name_len).bold(),
+        total(|s| s.selected),
+        total(|s| s.skipped),
+        total(|s| s.deleted),
+        color_failed(total(|s| s.failed)),
+    );
+}
+
+/// Formats a failure count, in red when anything failed.
+fn color_failed(failed: usize) -> ColoredString {
+    let failed_str = format!("{:>6}", failed);
+    if failed > 0 { failed_str.red() } else { failed_str.normal() }
+}
+
+/// Prints the tag report in the given format.
+///
+/// When `deleting`, tags that are kept are marked with the reason they were skipped.
+pub fn print_tags(tags: &[TagDetails], format: OutputFormat, deleting: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Text => {
+            let max_name_len = tags.iter().map(|t| t.name.len()).max().unwrap_or(10);
+            let selected_count = tags.iter().filter(|t| t.skipped.is_none()).count();
+
+            if !deleting {
+                println!("Found {} tags.", tags.len());
+            } else {
+                println!("Found {} tags, {} selected for deletion.", tags.len(), selected_count);
+            }
+            for tag in tags {
+                let name_str = format!("{:<width$}", tag.name, width = max_name_len);
+                let age_str = format!("{:>11}", time_utils::format_age(tag.age)).blue();
+                let kind_str = format!("{:<11}", if tag.annotated { "annotated" } else { "lightweight" }).cyan();
+                let tip_str = tag.tip.get(..7).unwrap_or(&tag.tip).dimmed();
+                let status_str = match tag.remote {
+                    Some(ref remote) => format!("on {}", remote),
+                    None => tag.status.clone(),
+                };
+                match tag.skipped {
+                    Some(ref why) => println!("- {}    {}    {}    {}    {}", name_str.yellow(), age_str, kind_str, tip_str, format!("skipped: {}", why).yellow()),
+                    None => println!("* {}    {}    {}    {}    {}", name_str.green(), age_str, kind_str, tip_str, status_str.dimmed()),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(io::stdout().lock(), tags)?;
+            println!();
+        }
+        OutputFormat::Csv => write_delimited(tags, b',')?,
+        OutputFormat::Tsv => write_delimited(tags, b'\t')?,
+    }
+    Ok(())
+}
+
+/// Prints the stash entries with their age, branch and diffstat.
+///
+/// When `dropping`, entries that are kept are marked with the reason they were skipped.
+pub fn print_stashes(stashes: &[StashDetails], dropping: bool) {
+    let max_branch_len = stashes.iter().map(|s| s.branch.len()).max().unwrap_or(10);
+    let max_stat_len = stashes.iter().map(|s| format_diffstat(s).len()).max().unwrap_or(0);
+
+    if dropping {
+        let selected_count = stashes.iter().filter(|s| s.skipped.is_none()).count();
+        println!("Found {} stash entries, {} selected to drop.", stashes.len(), selected_count);
+    } else {
+        println!("Found {} stash entries.", stashes.len());
+    }
+    for stash in stashes {
+        let index_str = format!("{:<10}", format!("stash@{{{}}}", stash.index));
+        let age_str = format!("{:>11}", time_utils::format_age(stash.age)).blue();
+        let branch_str = format!("{:<width$}", stash.branch, width = max_branch_len).cyan();
+        let stat_str = format!("{:<width$}", format_diffstat(stash), width = max_stat_len);
+        let message_str = if stash.applied { format!("{} (applied)", stash.message) } else { stash.message.clone() };
+        match stash.skipped {
+            Some(ref why) => println!("- {}    {}    {}    {}    {}", index_str.yellow(), age_str, branch_str, stat_str, format!("skipped: {}", why).yellow()),
+            None => println!("* {}    {}    {}    {}    {}", index_str.green(), age_str, branch_str, stat_str, message_str.dimmed()),
+        }
+    }
+}
+
+/// Prints the linked worktrees with their branch and last activity.
+///
+/// When `pruning`, worktrees that are kept are marked with the reason they were skipped.
+pub fn print_worktrees(worktrees: &[WorktreeDetails], pruning: bool) {
+    let max_name_len = worktrees.iter().map(|w| w.name.len()).max().unwrap_or(10);
+    let max_branch_len = worktrees.iter().map(|w| w.branch.as_deref().unwrap_or("(detached)").len()).max().unwrap_or(10);
+
+    if pruning {
+        let selected_count = worktrees.iter().filter(|w| w.skipped.is_none()).count();
+        println!("Found {} worktrees, {} selected to prune.", worktrees.len(), selected_count);
+    } else {
+        println!("Found {} worktrees.", worktrees.len());
+    }
+    for worktree in worktrees {
+        let name_str = format!("{:<width$}", worktree.name, width = max_name_len);
+        let age_str = format!("{:>11}", time_utils::format_age(worktree.age)).blue();
+        let branch_str = format!("{:<width$}", worktree.branch.as_deref().unwrap_or("(detached)"), width = max_branch_len).cyan();
+        let path_str = if worktree.missing { format!("{} (missing)", worktree.path).red() } else { worktree.path.dimmed() };
+        match worktree.skipped {
+            Some(ref why) if pruning => println!("- {}    {}    {}    {}    {}", name_str.yellow(), age_str, branch_str, path_str, format!("skipped: {}", why).yellow()),
+            _ => println!("* {}    {}    {}    {}    {}", name_str.green(), age_str, branch_str, path_str, worktree.reason.dimmed()),
+        }
+    }
+}
+
+/// Formats the diffstat of a stash entry, e.g. `3 files +10 -2`.
+fn format_diffstat(stash: &StashDetails) -> String {
+    let files = if stash.files == 1 { "file" } else { "files" };
+    format!("{} {} +{} -{}", stash.files, files, stash.insertions, stash.deletions)
+}
+
+/// Prints the list of selected branches, marking skipped ones with the reason.
+///
+/// When remote branches are listed, branches are grouped under a heading per remote.
+fn print_text(branches: &[BranchDetails], label: &str) {
+    let max_name_len = branches
+        .iter()
+        .map(|b| b.name.len())
+        .max()
+        .unwrap_or(10);
+
+    let max_counts_len = branches
+        .iter()
+        .map(|b| format_counts(b).len())
+        .max()
+        .unwrap_or(0);
+
+    let skipped_count = branches.iter().filter(|b| b.skipped.is_some()).count();
+
+    let grouped = branches.iter().any(|b| b.remote.is_some());
+
+    println!("Found {} {} branches.", branches.len() - skipped_count, label);
+    for (i, branch) in branches.iter().enumerate() {
+        if grouped && (i == 0 || branches[i - 1].remote != branch.remote) {
+            match branch.remote {
+                Some(ref remote) => println!("{}", format!("{}:", remote).bold()),
+                None => println!("{}", "local:".bold()),
+            }
+        }
+        let age_str = format!("{:>11}", time_utils::format_age(branch.age)).blue();
+        let tip_str = branch.tip.get(..7).unwrap_or(&branch.tip).dimmed();
+        let counts_str = format!("{:<width$}", format_counts(branch), width = max_counts_len).cyan();
+        match branch.skipped {
+            Some(ref why) => println!(
+                "- {}    {}    {}    {}    {}",
+                format!("{:<width$}", branch.name, width = max_name_len).yellow(),
+                age_str,
+                tip_str,
+                counts_str,
+                format!("skipped: {}", why).yellow(),
+            ),
+            None => println!(
+                "* {}    {}    {}    {}    {}",
+                format!("{:<width$}", branch.name, width = max_name_len).green(),
+                age_str,
+                tip_str,
+                counts_str,
+                branch.reason.dimmed(),
+            ),
+        }
+    }
+}
+
+/// Formats how far a branch is ahead and behind the main branch and its upstream,
+/// e.g. `+2 -10 main, +1 -0 origin/feature`.
+fn format_counts(branch: &BranchDetails) -> String {
+    let mut counts = Vec::new();
+    if let (Some(base), Some(ahead), Some(behind)) = (&branch.base, branch.base_ahead, branch.base_behind) {
+        counts.push(format!("+{} -{} {}", ahead, behind, base));
+    }
+    if let (Some(upstream), Some(ahead), Some(behind)) = (&branch.upstream, branch.ahead, branch.behind) {
+        counts.push(format!("+{} -{} {}", ahead, behind, upstream));
+    }
+    counts.join(", ")
+}
+
+fn write_delimited<T: Serialize>(rows: &[T], delimiter: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(io::stdout().lock());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use log::debug;
+
+use colored::*;
+use indicatif::ProgressBar;
+use git2::{Commit, ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+use crate::clean::{DeleteSummary, Deleter, Verb};
+use crate::io_utils;
+
+/// A stash entry.
+#[derive(Debug, Default)]
+pub struct StashDetails {
+    pub index: usize,
+    pub message: String,
+    pub branch: String,
+    pub age: u64,
+    pub tip: String,
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub applied: bool,
+    pub skipped: Option<String>,
+}
+
+/// Which stash entries are selected for dropping.
+pub struct StashSelection {
+    /// Stashes older than the given duration.
+    pub older_than: Option<Duration>,
+    /// Stashes whose changes are already on the current branch.
+    pub applied: bool,
+}
+
+impl StashSelection {
+    fn is_empty(&self) -> bool {
+        self.older_than.is_none() && !self.applied
+    }
+}
+
+/// Lists every stash entry, newest first.
+///
+/// Entries not matching the selection are marked as skipped. When nothing is
+/// selected every entry is listed as is.
+pub fn scan_stashes(repo: &mut Repository, selection: &StashSelection) -> Result<Vec<StashDetails>, git2::Error> {
+    let mut entries = Vec::new();
+    repo.stash_foreach(|index, message, oid| {
+        entries.push((index, message.to_string(), *oid));
+        true
+    })?;
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let head_tree = repo.head().and_then(|h| h.peel_to_tree()).ok();
+    let mut stashes = Vec::new();
+
+    for (index, message, oid) in entries {
+        let commit = repo.find_commit(oid)?;
+        let time = commit.time().seconds() as u64;
+        let (files, insertions, deletions, applied) = stash_changes(repo, &commit, head_tree.as_ref())?;
+
+        let old = selection.older_than.is_some_and(|max_age| now.saturating_sub(time) > max_age.as_secs());
+        let skipped = if selection.is_empty() || old || (selection.applied && applied) {
+            None
+        } else if selection.applied && selection.older_than.is_some() {
+            Some("recent and not applied".to_string())
+        } else if selection.applied {
+            Some("not applied".to_string())
+        } else {
+            Some("recent".to_string())
+        };
+
+        stashes.push(StashDetails {
+            index,
+            branch: stash_branch(&message).unwrap_or_default().to_string(),
+            message,
+            age: now.saturating_sub(time) / 86400,
+            tip: oid.to_string(),
+            files,
+            insertions,
+            deletions,
+            applied,
+            skipped,
+        });
+    }
+
+    debug!("Found {} stash entries.", stashes.len());
+    Ok(stashes)
+}
+
+/// Drops the given stash entries, asking for confirmation unless `yes` is set.
+///
+/// Entries are dropped from the highest index down so the remaining indices
+/// stay valid. The id of each dropped stash is printed so it can still be
+/// recovered with `git stash store`.
+pub fn drop_stashes(repo: &mut Repository, stashes: &[StashDetails], yes: bool, progress: Option<&ProgressBar>, verbose: bool) -> DeleteSummary {
+    let mut selected: Vec<&StashDetails> = stashes.iter().filter(|s| s.skipped.is_none()).collect();
+    selected.sort_by_key(|s| std::cmp::Reverse(s.index));
+
+    Deleter::new(Verb::Drop, yes, progress, verbose).run_all(
+        selected,
+        |stash| format!("stash@{{{}}}", stash.index),
+        |stash| format!("Drop stash@{{{}}} ({})?", stash.index, stash.message),
+        |stash| {
+            let result = repo.stash_drop(stash.index).map_err(|e| e.message().to_string());
+            if result.is_ok() && !verbose {
+                io_utils::print_error(progress, &format!("Dropped stash@{{{}}} ({})", stash.index, stash.tip).dimmed());
+            }
+            Some(result)
+        },
+    )
+}
+
+/// Returns the branch a stash was made on, parsed from its message
+/// (`WIP on <branch>: ...` or `On <branch>: ...`).
+fn stash_branch(message: &str) -> Option<&str> {
+    let rest = message.strip_prefix("WIP on ").or_else(|| message.strip_prefix("On "))?;
+    rest.split_once(':').map(|(branch, _)| branch)
+}
+
+/// Computes the diffstat of a stash against the commit it was made on, and
+/// whether every change it holds, including untracked files saved with
+/// `git stash -u`, is already in `head_tree`.
+///
+/// A stash holding no changes at all is never considered applied.
+fn stash_changes(repo: &Repository, stash: &Commit, head_tree: Option<&git2::Tree>) -> Result<(usize, usize, usize, bool), git2::Error> {
+    let stash_tree = stash.tree()?;
+    let base_tree = stash.parent(0)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&stash_tree), None)?;
+    let stats = diff.stats()?;
+
+    // Content of each changed path in the stash, or `None` if the stash deleted it
+    let mut changes: Vec<(PathBuf, Option<Oid>)> = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+        .map(|path| {
+            let id = entry_id(&stash_tree, &path);
+            (path, id)
+        })
+        .collect();
+
+    // Untracked files are kept in the tree of a third parent
+    if stash.parent_count() > 2 {
+        stash.parent(2)?.tree()?.walk(TreeWalkMode::PreOrder, |root, entry| {
+            if entry.kind() == Some(ObjectType::Blob)
+                && let Some(name) = entry.name()
+            {
+                changes.push((Path::new(root).join(name), Some(entry.id())));
+            }
+            TreeWalkResult::Ok
+        })?;
+    }
+
+    // A change is applied when the current branch has the same content at that path
+    let applied = !changes.is_empty()
+        && head_tree.is_some_and(|head_tree| changes.iter().all(|(path, id)| entry_id(head_tree, path) == *id));
+
+    Ok((stats.files_changed(), stats.insertions(), stats.deletions(), applied))
+}
+
+fn entry_id(tree: &git2::Tree, path: &Path) -> Option<Oid> {
+    tree.get_path(path).ok().map(|e| e.id())
+}
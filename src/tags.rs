@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use log::debug;
+
+use glob::Pattern;
+use serde::Serialize;
+use indicatif::ProgressBar;
+use git2::{ObjectType, Oid, Reference, Repository};
+use crate::clean::{DeleteSummary, Deleter, Verb};
+use crate::git_utils;
+use crate::trash::Trash;
+
+/// A tag found in the repository or on the remote.
+///
+/// The kind is `tag` for local tags and `remote-tag` for tags on the remote,
+/// matching the kinds recorded in the trash.
+#[derive(Debug, Default, Serialize)]
+pub struct TagDetails {
+    pub name: String,
+    pub kind: String,
+    pub remote: Option<String>,
+    pub annotated: bool,
+    pub age: u64,
+    pub tip: String,
+    pub target: String,
+    pub status: String,
+    pub skipped: Option<String>,
+}
+
+/// Which tags are listed and which of them are selected for deletion.
+pub struct TagSelection<'a> {
+    pub pattern: Option<Pattern>,
+    pub stale: Option<Duration>,
+    pub remote: &'a str,
+    /// Tags on the remote by name, or `None` when the remote was not contacted.
+    pub remote_tags: Option<&'a HashMap<String, Oid>>,
+    /// Selects local tags that do not exist on the remote.
+    pub local_only: bool,
+    /// Selects tags on the remote instead of local tags.
+    pub delete_remote: bool,
+}
+
+/// Lists the tags matching the selection, oldest first.
+///
+/// Local tags are only marked as skipped when `local_only` is set, otherwise
+/// the caller decides whether the listed tags are deleted.
+pub fn scan_tags(repo: &Repository, selection: &TagSelection) -> Result<Vec<TagDetails>, git2::Error> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let mut tags = Vec::new();
+
+    let matches = |name: &str| selection.pattern.as_ref().is_none_or(|p| p.matches(name));
+
+    if selection.delete_remote {
+        let Some(remote_tags) = selection.remote_tags else {
+            return Ok(tags);
+        };
+        for (name, oid) in remote_tags.iter().filter(|(name, _)| matches(name)) {
+            let mut tag = TagDetails {
+                name: name.clone(),
+                kind: "remote-tag".to_string(),
+                remote: Some(selection.remote.to_string()),
+                tip: oid.to_string(),
+                status: "remote".to_string(),
+                ..Default::default()
+            };
+            // The backup reference needs the tag object, so tags that were never fetched are left alone
+            match describe_object(repo, *oid) {
+                Some((annotated, target, time)) => {
+                    tag.annotated = annotated;
+                    tag.target = target.to_string();
+                    tag.age = now.saturating_sub(time as u64) / 86400;
+                    if selection.stale.is_some_and(|stale| now.saturating_sub(time as u64) <= stale.as_secs()) {
+                        continue;
+                    }
+                }
+                None => tag.skipped = Some("not fetched".to_string()),
+            }
+            tags.push(tag);
+        }
+    } else {
+        for reference in repo.references_glob("refs/tags/*")? {
+            let reference = reference?;
+            let Some(name) = reference.shorthand().map(str::to_string) else {
+                continue;
+            };
+            if !matches(&name) {
+                continue;
+            }
+            let Some((tip, annotated, target, time)) = describe_reference(repo, &reference) else {
+                debug!("Skipping tag {} with no readable target.", name);
+                continue;
+            };
+            if selection.stale.is_some_and(|stale| now.saturating_sub(time as u64) <= stale.as_secs()) {
+                continue;
+            }
+
+            let status = match selection.remote_tags.map(|remote_tags| remote_tags.get(&name)) {
+                None => "unknown",
+                Some(None) => "local only",
+                Some(Some(oid)) if *oid == tip => "remote",
+                Some(Some(_)) => "differs",
+            };
+            let skipped = match (selection.local_only, status) {
+                (true, "local only") => None,
+                (true, "remote") => Some(format!("exists on {}", selection.remote)),
+                (true, _) => Some(format!("{} on {}", status, selection.remote)),
+                (false, _) => None,
+            };
+
+            tags.push(TagDetails {
+                name,
+                kind: "tag".to_string(),
+                remote: None,
+                annotated,
+                age: now.saturating_sub(time as u64) / 86400,
+                tip: tip.to_string(),
+                target: target.to_string(),
+                status: status.to_string(),
+                skipped,
+            });
+        }
+    }
+
+    tags.sort_by(|a, b| b.age.cmp(&a.age).then(a.name.cmp(&b.name)));
+    debug!("Found {} tags.", tags.len());
+    Ok(tags)
+}
+
+/// Deletes the given tags, asking for confirmation unless `yes` is set.
+///
+/// Every deleted tag is backed up in the trash first, the same way branches are.
+pub fn delete_tags(repo: &Repository, tags: &[TagDetails], yes: bool, progress: Option<&ProgressBar>, verbose: bool) -> DeleteSummary {
+    let trash = Trash::open(repo);
+
+    let prompt = |tag: &TagDetails| match tag.remote {
+        Some(ref remote) => format!("Delete tag {} from {}?", tag.name, remote),
+        None => format!("Delete tag {}?", tag.name),
+    };
+    Deleter::new(Verb::Delete, yes, progress, verbose).run_all(
+        tags.iter().filter(|t| t.skipped.is_none()),
+        |tag| format!("{} {}", tag.kind.replace('-', " "), tag.name),
+        prompt,
+        |tag| {
+            let result = trash.record(repo, &tag.name, &tag.kind, tag.remote.as_deref(), &tag.tip).and_then(|entry| {
+                let deleted = match tag.remote {
+                    Some(ref remote) => git_utils::delete_remote_tag(repo, remote, &tag.name, progress),
+                    None => git_utils::delete_local_tag(repo, &tag.name),
+                };
+                // A tag that was not deleted needs no backup
+                if deleted.is_err() {
+                    trash.remove(repo, &[entry])?;
+                }
+                Ok(deleted?)
+            });
+            Some(result)
+        },
+    )
+}
+
+/// Returns the tag's own object id, whether it is annotated, the object it
+/// points to and the time it was made.
+fn describe_reference(repo: &Repository, reference: &Reference) -> Option<(Oid, bool, Oid, i64)> {
+    let tip = reference.target()?;
+    let (annotated, target, time) = describe_object(repo, tip)?;
+    Some((tip, annotated, target, time))
+}
+
+/// Describes the object a tag points to.
+///
+/// Annotated tags are dated by their tagger, falling back to the tagged commit
+/// like lightweight tags are.
+fn describe_object(repo: &Repository, oid: Oid) -> Option<(bool, Oid, i64)> {
+    let object = repo.find_object(oid, None).ok()?;
+    let annotated = object.kind() == Some(ObjectType::Tag);
+    let tagger_time = object.as_tag().and_then(|t| t.tagger()).map(|t| t.when().seconds());
+
+    // Follow chains of annotated tags down to the tagged object
+    let mut target = object;
+    while let Some(tag) = target.as_tag() {
+        target = tag.target().ok()?;
+    }
+    let commit_time = target.as_commit().map(|c| c.time().seconds());
+
+    Some((annotated, target.id(), tagger_time.or(commit_time).unwrap_or(0)))
+}
@@ -0,0 +1,251 @@
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// Units accepted in durations, with their length in seconds
+const UNITS: [(&[&str], u64); 7] = [
+    (&["s", "sec", "secs", "second", "seconds"], 1),
+    (&["m", "min", "mins", "minute", "minutes"], MINUTE),
+    (&["h", "hr", "hrs", "hour", "hours"], HOUR),
+    (&["d", "day", "days"], DAY),
+    (&["w", "wk", "wks", "week", "weeks"], WEEK),
+    (&["mo", "month", "months"], MONTH),
+    (&["y", "yr", "yrs", "year", "years"], YEAR),
+];
+
+/// Parse a duration such as `30`, `36h`, `2w`, `3mo`, `1.5y` or `2 weeks ago`
+///
+/// A number without a unit is a number of days.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let trimmed = input.trim().to_lowercase();
+    let trimmed = trimmed.strip_suffix("ago").unwrap_or(&trimmed).trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let number: f64 = number.parse().map_err(|_| format!("Invalid duration '{}'", input))?;
+    let unit = unit.trim();
+    let unit_secs = if unit.is_empty() {
+        DAY
+    } else {
+        UNITS
+            .iter()
+            .find(|(names, _)| names.contains(&unit))
+            .map(|(_, secs)| *secs)
+            .ok_or_else(|| format!("Unknown duration unit '{}' in '{}'", unit, input))?
+    };
+
+    Duration::try_from_secs_f64(number * unit_secs as f64).map_err(|_| format!("Duration '{}' is too long", input))
+}
+
+/// Parse a date as a Unix time, the way git's approxidate does
+///
+/// Accepts absolute dates such as `2026-01-01` or `2026-01-01 14:30`, a year
+/// such as `2026` for its first day, RFC 3339 timestamps, `now`, `today`,
+/// `yesterday`, `last week` and relative expressions such as `2 weeks ago` or
+/// `3mo`. Other bare numbers are rejected rather than read as days.
+pub fn parse_date(input: &str) -> Result<i64, String> {
+    let trimmed = input.trim();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(date.timestamp());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(trimmed, format) {
+            return local_timestamp(date);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(trimmed, "%Y-%m-%d") {
+        return local_timestamp(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    if !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_digit()) {
+        return match trimmed.parse().ok().filter(|_| trimmed.len() == 4).and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1)) {
+            Some(date) => local_timestamp(date.and_hms_opt(0, 0, 0).unwrap()),
+            None => Err(format!("Invalid date '{}', expected a year or a date such as 2026-01-01", input)),
+        };
+    }
+
+    let now = Local::now();
+    let lower = trimmed.to_lowercase();
+    let midnight = |date: NaiveDate| local_timestamp(date.and_hms_opt(0, 0, 0).unwrap());
+    match lower.as_str() {
+        "now" => return Ok(now.timestamp()),
+        "today" => return midnight(now.date_naive()),
+        "yesterday" => return midnight(now.date_naive().pred_opt().unwrap()),
+        _ => {}
+    }
+
+    let relative = match lower.strip_prefix("last ") {
+        Some(unit) => parse_duration(&format!("1 {}", unit.trim())),
+        None => parse_duration(&lower),
+    };
+    relative
+        .map(|ago| now.timestamp() - ago.as_secs() as i64)
+        .map_err(|_| format!("Invalid date '{}'", input))
+}
+
+/// Format a duration in its largest unit, e.g. `3 hours` or `1.5 years`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (unit_secs, unit) = match secs {
+        s if s >= YEAR => (YEAR, "year"),
+        s if s >= MONTH => (MONTH, "month"),
+        s if s >= WEEK => (WEEK, "week"),
+        s if s >= DAY => (DAY, "day"),
+        s if s >= HOUR => (HOUR, "hour"),
+        s if s >= MINUTE => (MINUTE, "minute"),
+        _ => (1, "second"),
+    };
+
+    // Show one decimal place for small counts, e.g. `1.5 years`
+    let count = secs as f64 / unit_secs as f64;
+    let rounded = (count * 10.0).round() / 10.0;
+    let count = if rounded < 10.0 && rounded.fract() != 0.0 {
+        format!("{:.1}", rounded)
+    } else {
+        format!("{}", count.round())
+    };
+
+    if count == "1" {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+/// Format an age in whole days, e.g. `3 weeks`
+pub fn format_age(days: u64) -> String {
+    if days == 0 {
+        "< 1 day".to_string()
+    } else {
+        format_duration(Duration::from_secs(days * DAY))
+    }
+}
+
+/// Return how long ago the given Unix time was
+pub fn since(timestamp: u64) -> Duration {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    Duration::from_secs(now.saturating_sub(timestamp))
+}
+
+/// Format a Unix time as a UTC date and time, e.g. `2026-01-31 14:05 UTC`
+pub fn format_timestamp(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn local_timestamp(date: NaiveDateTime) -> Result<i64, String> {
+    Local
+        .from_local_datetime(&date)
+        .earliest()
+        .map(|d| d.timestamp())
+        .ok_or_else(|| format!("Invalid local time '{}'", date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30 * DAY)));
+        assert_eq!(parse_duration("36h"), Ok(Duration::from_secs(36 * HOUR)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(2 * WEEK)));
+        assert_eq!(parse_duration("3mo"), Ok(Duration::from_secs(3 * MONTH)));
+        assert_eq!(parse_duration("1.5y"), Ok(Duration::from_secs(YEAR + YEAR / 2)));
+        assert_eq!(parse_duration("10 minutes"), Ok(Duration::from_secs(10 * MINUTE)));
+    }
+
+    #[test]
+    fn parse_duration_ago() {
+        assert_eq!(parse_duration("2 weeks ago"), Ok(Duration::from_secs(2 * WEEK)));
+        assert_eq!(parse_duration(" 3 Days Ago "), Ok(Duration::from_secs(3 * DAY)));
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("weeks").is_err());
+        assert!(parse_duration("-3d").is_err());
+        assert!(parse_duration("1.2.3d").is_err());
+        assert!(parse_duration("3 fortnights").is_err());
+    }
+
+    #[test]
+    fn parse_duration_overflow() {
+        assert!(parse_duration("99999999999999999999y").is_err());
+    }
+
+    #[test]
+    fn parse_date_absolute() {
+        assert_eq!(parse_date("2026-01-01T12:00:00Z"), Ok(1767268800));
+        assert_eq!(parse_date("2026-01-01T12:00:00+02:00"), Ok(1767261600));
+
+        let midnight = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(parse_date("2026-01-01"), local_timestamp(midnight));
+        let afternoon = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(14, 30, 0).unwrap();
+        assert_eq!(parse_date("2026-01-01 14:30"), local_timestamp(afternoon));
+        assert_eq!(parse_date("2026-01-01T14:30:00"), local_timestamp(afternoon));
+    }
+
+    #[test]
+    fn parse_date_relative() {
+        let now = Local::now().timestamp();
+        let close_to = |date: Result<i64, String>, expected: i64| (date.unwrap() - expected).abs() <= 5;
+
+        assert!(close_to(parse_date("now"), now));
+        assert!(close_to(parse_date("2 weeks ago"), now - 2 * WEEK as i64));
+        assert!(close_to(parse_date("last week"), now - WEEK as i64));
+        assert!(close_to(parse_date("3mo"), now - 3 * MONTH as i64));
+
+        let today = parse_date("today").unwrap();
+        assert!(today <= now && now - today < DAY as i64 + HOUR as i64);
+        let yesterday = parse_date("yesterday").unwrap();
+        assert!(yesterday < today && today - yesterday <= DAY as i64 + HOUR as i64);
+    }
+
+    #[test]
+    fn parse_date_year() {
+        let new_year = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(parse_date("2026"), local_timestamp(new_year));
+        assert_eq!(parse_date(" 1999 "), local_timestamp(NaiveDate::from_ymd_opt(1999, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn parse_date_rejects_bare_numbers() {
+        assert!(parse_date("30").is_err());
+        assert!(parse_date("202601").is_err());
+        assert!(parse_date("0").is_err());
+    }
+
+    #[test]
+    fn parse_date_invalid() {
+        assert!(parse_date("2026-13-01").is_err());
+        assert!(parse_date("last fortnight").is_err());
+        assert!(parse_date("soon").is_err());
+    }
+
+    #[test]
+    fn format_duration_units() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0 seconds");
+        assert_eq!(format_duration(Duration::from_secs(1)), "1 second");
+        assert_eq!(format_duration(Duration::from_secs(3 * HOUR)), "3 hours");
+        assert_eq!(format_duration(Duration::from_secs(DAY)), "1 day");
+        assert_eq!(format_duration(Duration::from_secs(2 * WEEK)), "2 weeks");
+        assert_eq!(format_duration(Duration::from_secs(YEAR + YEAR / 2)), "1.5 years");
+        assert_eq!(format_duration(Duration::from_secs(12 * YEAR)), "12 years");
+    }
+
+    #[test]
+    fn format_duration_rounds() {
+        assert_eq!(format_duration(Duration::from_secs(90 * MINUTE)), "1.5 hours");
+        assert_eq!(format_duration(Duration::from_secs(DAY + MINUTE)), "1 day");
+        assert_eq!(format_duration(Duration::from_secs(15 * HOUR + 40 * MINUTE)), "16 hours");
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use git2::{Oid, Repository};
+use crate::git_utils;
+
+/// Prefix of the references keeping deleted branch tips reachable.
+const TRASH_REF_PREFIX: &str = "refs/purgit/trash";
+
+/// A deleted branch or tag recorded in the journal.
+///
+/// The kind is `local` or `remote` for branches, and `tag` or `remote-tag` for tags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Unix time of the run that deleted the reference.
+    pub run: u64,
+    pub name: String,
+    pub kind: String,
+    pub remote: Option<String>,
+    pub tip: String,
+}
+
+impl TrashEntry {
+    /// Describes what was deleted, e.g. `local branch` or `remote tag`.
+    pub fn describe(&self) -> String {
+        match self.kind.as_str() {
+            "tag" => "tag".to_string(),
+            "remote-tag" => "remote tag".to_string(),
+            kind => format!("{} branch", kind),
+        }
+    }
+
+    /// Returns the reference keeping the deleted tip reachable.
+    pub fn backup_ref(&self) -> String {
+        format!("{}/{}/{}/{}", TRASH_REF_PREFIX, self.run, self.kind, self.name)
+    }
+}
+
+/// The journal of deleted branches and tags kept under `.git/purgit/`.
+pub struct Trash {
+    path: PathBuf,
+    run: u64,
+}
+
+impl Trash {
+    /// Opens the journal of the given repository, starting a new run.
+    ///
+    /// The journal is shared by every worktree of the repository.
+    pub fn open(repo: &Repository) -> Self {
+        Trash {
+            path: repo.commondir().join("purgit").join("journal.jsonl"),
+            run: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
+        }
+    }
+
+    /// Backs up a branch or tag before it is deleted.
+    ///
+    /// The tip is kept reachable through a backup reference and the reference
+    /// is appended to the journal.
+    pub fn record(&self, repo: &Repository, name: &str, kind: &str, remote: Option<&str>, tip: &str) -> Result<TrashEntry, Box<dyn std::error::Error>> {
+        let entry = TrashEntry {
+            run: self.run,
+            name: name.to_string(),
+            kind: kind.to_string(),
+            remote: remote.map(str::to_string),
+            tip: tip.to_string(),
+        };
+
+        let backup_ref = entry.backup_ref();
+        repo.reference(&backup_ref, Oid::from_str(&entry.tip)?, true, &format!("purgit: back up {}", entry.name))?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut journal = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(journal, "{}", serde_json::to_string(&entry)?)?;
+
+        debug!("Backed up {} to {}.", entry.name, backup_ref);
+        Ok(entry)
+    }
+
+    /// Reads every entry in the journal, oldest first.
+    pub fn entries(&self) -> Result<Vec<TrashEntry>, Box<dyn std::error::Error>> {
+        if !self.path.is_file() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("Invalid journal {}: {}", self.path.display(), e).into()))
+            .collect()
+    }
+
+    /// Recreates a deleted branch or tag from its backup reference.
+    ///
+    /// Local branches and tags are recreated in the repository, and remote
+    /// branches and tags are pushed back to their remote.
+    pub fn restore(&self, repo: &Repository, entry: &TrashEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let backup_ref = entry.backup_ref();
+        let backup = repo.find_reference(&backup_ref)?;
+
+        match entry.kind.as_str() {
+            "local" => {
+                repo.branch(&entry.name, &backup.peel_to_commit()?, false)?;
+            }
+            "tag" => {
+                let target = backup.target().ok_or("Backup reference has no target")?;
+                repo.reference(&format!("refs/tags/{}", entry.name), target, false, "purgit: restore tag")?;
+            }
+            "remote-tag" => {
+                let remote = entry.remote.as_deref().ok_or("Remote tag has no remote")?;
+                git_utils::push_remote_tag(repo, remote, &entry.name, &backup_ref)?;
+            }
+            _ => git_utils::push_remote_branch(repo, &entry.name, &backup_ref)?,
+        }
+
+        debug!("Restored {} {} at {}.", entry.kind, entry.name, entry.tip);
+        Ok(())
+    }
+
+    /// Removes the given entries from the journal along with their backup references.
+    pub fn remove(&self, repo: &Repository, removed: &[TrashEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        let is_removed = |entry: &TrashEntry| removed.iter().any(|r| r.run == entry.run && r.kind == entry.kind && r.name == entry.name);
+
+        for entry in removed {
+            if let Ok(mut reference) = repo.find_reference(&entry.backup_ref()) {
+                reference.delete()?;
+            }
+        }
+
+        let mut contents = String::new();
+        for entry in self.entries()?.into_iter().filter(|e| !is_removed(e)) {
+            contents.push_str(&serde_json::to_string(&entry)?);
+            contents.push('\n');
+        }
+        fs::write(&self.path, contents)?;
+
+        debug!("Removed {} entries from the trash.", removed.len());
+        Ok(())
+    }
+}
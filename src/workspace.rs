@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::debug;
+
+/// Outcome of cleaning one repository in multi-repository mode.
+#[derive(Debug, Default)]
+pub struct RepoSummary {
+    pub name: String,
+    pub path: PathBuf,
+    pub selected: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+/// Finds every repository under `dir`, sorted by path.
+///
+/// A directory is a repository when it contains a `.git` directory or file, or
+/// when it is a bare repository itself. Repositories are not searched for
+/// nested repositories, and hidden directories are not searched at all.
+pub fn find_repositories(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut repos = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        if dir.join(".git").exists() || is_bare_repository(&dir) {
+            repos.push(dir);
+            continue;
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Skipping {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push(entry.path());
+            }
+        }
+    }
+
+    repos.sort();
+    debug!("Found {} repositories under {}.", repos.len(), dir.display());
+    Ok(repos)
+}
+
+/// Returns whether the directory looks like a bare repository.
+fn is_bare_repository(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// Reads a list of repository paths, one per line.
+///
+/// Blank lines and lines starting with `#` are ignored, a leading `~/` is
+/// expanded to the home directory, and relative paths are taken relative to
+/// the list file.
+pub fn read_repository_list(file: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(file).map_err(|e| format!("Could not read {}: {}", file.display(), e))?;
+    let base = file.parent().unwrap_or(Path::new("."));
+
+    let repos = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match (line.strip_prefix("~/"), std::env::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => base.join(line),
+        })
+        .collect();
+    Ok(repos)
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use log::debug;
+
+use indicatif::ProgressBar;
+use git2::{Oid, Repository, StatusOptions, WorktreePruneOptions};
+use crate::clean::{DeleteSummary, Deleter, Verb};
+use crate::git_utils::{self, MergeStatus};
+
+/// A linked worktree.
+#[derive(Debug, Default)]
+pub struct WorktreeDetails {
+    pub name: String,
+    pub path: String,
+    pub branch: Option<String>,
+    /// Days since anything last happened in the worktree.
+    pub age: u64,
+    pub missing: bool,
+    pub reason: String,
+    pub skipped: Option<String>,
+}
+
+/// Lists every linked worktree, selecting the ones to prune.
+///
+/// Worktrees whose directory is missing are selected when `prune` is set, and
+/// worktrees whose branch is merged into `base` are selected when a base is given.
+/// Locked worktrees and worktrees with uncommitted changes are never selected.
+pub fn scan_worktrees(repo: &Repository, prune: bool, base: Option<&(String, Oid)>) -> Result<Vec<WorktreeDetails>, git2::Error> {
+    let mut worktrees = Vec::new();
+    let mut patch_ids = HashMap::new();
+
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        let admin_dir = repo.commondir().join("worktrees").join(name);
+        let branch = git_utils::worktree_branch(repo, name);
+        let missing = !worktree.path().exists();
+
+        let merged = match (&branch, base) {
+            (Some(branch), Some((_, base))) => match repo.find_branch(branch, git2::BranchType::Local).ok().and_then(|b| b.get().target()) {
+                Some(tip) => git_utils::merge_status(repo, tip, *base, &mut patch_ids)? != MergeStatus::Unmerged,
+                None => false,
+            },
+            _ => false,
+        };
+
+        let (reason, selected) = match (missing, merged) {
+            (true, _) => ("directory missing".to_string(), prune),
+            (false, true) => (format!("merged into {}", base.map(|(name, _)| name.as_str()).unwrap_or_default()), true),
+            (false, false) => (String::new(), false),
+        };
+
+        let skipped = if !selected {
+            Some(if reason.is_empty() { "in use".to_string() } else { reason.clone() })
+        } else if worktree.is_locked().is_ok_and(|l| !matches!(l, git2::WorktreeLockStatus::Unlocked)) {
+            Some("locked".to_string())
+        } else if !missing && has_changes(worktree.path()) {
+            Some("uncommitted changes".to_string())
+        } else {
+            None
+        };
+
+        worktrees.push(WorktreeDetails {
+            name: name.to_string(),
+            path: worktree.path().display().to_string(),
+            branch,
+            age: last_activity(&admin_dir, worktree.path()).map(|age| age.as_secs() / 86400).unwrap_or(0),
+            missing,
+            reason,
+            skipped,
+        });
+    }
+
+    debug!("Found {} linked worktrees.", worktrees.len());
+    Ok(worktrees)
+}
+
+/// Prunes the given worktrees, asking for confirmation unless `yes` is set.
+///
+/// The working tree directory of a merged worktree is removed along with its
+/// administrative files. Its branch is left in place for `purgit clean`.
+pub fn prune_worktrees(repo: &Repository, worktrees: &[WorktreeDetails], yes: bool, progress: Option<&ProgressBar>, verbose: bool) -> DeleteSummary {
+    Deleter::new(Verb::Prune, yes, progress, verbose).run_all(
+        worktrees.iter().filter(|w| w.skipped.is_none()),
+        |details| format!("worktree {}", details.name),
+        |details| format!("Prune worktree {} ({})?", details.name, details.path),
+        |details| {
+            let result = repo.find_worktree(&details.name).and_then(|worktree| {
+                worktree.prune(Some(WorktreePruneOptions::new().valid(true).working_tree(!details.missing)))
+            });
+            Some(result.map_err(|e| e.message().to_string()))
+        },
+    )
+}
+
+/// Returns how long ago the worktree's HEAD, index or reflog last changed.
+fn last_activity(admin_dir: &Path, worktree_dir: &Path) -> Option<Duration> {
+    let modified = [admin_dir.join("HEAD"), admin_dir.join("index"), admin_dir.join("logs").join("HEAD"), worktree_dir.to_path_buf()]
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()?;
+    Some(SystemTime::now().duration_since(modified).unwrap_or_default())
+}
+
+/// Returns whether the worktree has uncommitted or untracked changes.
+///
+/// A worktree that cannot be read is treated as having changes.
+fn has_changes(worktree_dir: &Path) -> bool {
+    let Ok(repo) = Repository::open(worktree_dir) else {
+        return true;
+    };
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).include_ignored(false);
+    repo.statuses(Some(&mut options)).map(|s| !s.is_empty()).unwrap_or(true)
+}